@@ -0,0 +1,586 @@
+//! Loads, caches and hot reloads game files
+//!
+//! # Usage
+//!
+//! ```no_run
+//!# use simple_game_utils::prelude::*;
+//!# fn main() {
+//!# let timing = Timing::new(240);
+//! let mut assets = AssetManager::new("assets");
+//! let map: Handle<TilemapFile> = assets.load("maps/temple.ron").unwrap(); //also loads the tileset
+//! let mut tilemap = assets.build_tilemap(&map, (200, 200)).unwrap();
+//!
+//! let mut reload_timer = Timer::new(1.0);
+//! loop {
+//!     if reload_timer.update(&timing) {
+//!         for event in assets.poll_changes() {
+//!             if event.affects(map.id()) {
+//!                 tilemap = assets.build_tilemap(&map, (200, 200)).unwrap();
+//!             }
+//!         }
+//!     }
+//!# break;
+//! }
+//!# }
+//! ```
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::SystemTime;
+
+use ici_files::prelude::*;
+
+use crate::prelude::*;
+
+/// Files that can be loaded by [AssetManager]
+pub trait Asset: Any + Sized {
+    /// Type name used in errors
+    const NAME: &'static str;
+
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, GameUtilError>;
+}
+
+/// Sound file contents, not decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoundAsset {
+    pub bytes: Vec<u8>,
+}
+
+impl Asset for SoundAsset {
+    const NAME: &'static str = "sound";
+
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, GameUtilError> {
+        Ok(SoundAsset { bytes })
+    }
+}
+
+/// Binary (see [crate::tiles::binary]) or RON
+impl Asset for TilemapFile {
+    const NAME: &'static str = "tilemap";
+
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, GameUtilError> {
+        if has_magic(&bytes, TILEMAP_MAGIC) {
+            TilemapFile::from_binary(&bytes)
+        } else {
+            ron::de::from_bytes(&bytes).map_err(|e| GameUtilError::Deserializing(e.to_string()))
+        }
+    }
+}
+
+/// Binary (see [crate::tiles::binary]) or RON
+impl Asset for IciTileset {
+    const NAME: &'static str = "tileset";
+
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, GameUtilError> {
+        if has_magic(&bytes, TILESET_MAGIC) {
+            IciTileset::from_binary(&bytes)
+        } else {
+            ron::de::from_bytes(&bytes).map_err(|e| GameUtilError::Deserializing(e.to_string()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct AssetId(u64);
+
+/// Reference to a loaded asset
+///
+/// Assets stay loaded while any handles for them exist, see [AssetManager::remove_unused]
+pub struct Handle<T: Asset> {
+    id: AssetId,
+    token: Rc<()>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Asset> Handle<T> {
+    fn new(id: AssetId, token: Rc<()>) -> Self {
+        Self {
+            id,
+            token,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn id(&self) -> AssetId {
+        self.id
+    }
+}
+
+impl<T: Asset> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle::new(self.id, self.token.clone())
+    }
+}
+
+impl<T: Asset> Debug for Handle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle<{}>({})", T::NAME, self.id.0)
+    }
+}
+
+impl<T: Asset> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T: Asset> Eq for Handle<T> {}
+
+impl<T: Asset> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+/// Result of [AssetManager::poll_changes]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetEvent {
+    /// File changed and was reloaded
+    Reloaded(AssetId),
+    /// File changed but couldn't be loaded, the previous version is still used
+    ReloadFailed(AssetId, String),
+    /// An asset `asset` depends on was reloaded
+    DependencyChanged { asset: AssetId, dependency: AssetId },
+}
+
+impl AssetEvent {
+    /// Returns true if `id` or one of its dependencies was reloaded
+    pub fn affects(&self, id: AssetId) -> bool {
+        match self {
+            AssetEvent::Reloaded(asset) => *asset == id,
+            AssetEvent::ReloadFailed(_, _) => false,
+            AssetEvent::DependencyChanged { asset, .. } => *asset == id,
+        }
+    }
+}
+
+type Loader = fn(Vec<u8>) -> Result<Box<dyn Any>, GameUtilError>;
+
+struct Entry {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    data: Box<dyn Any>,
+    reload: Loader,
+    refs: Weak<()>,
+    /// keeps dependencies loaded
+    dependencies: Vec<(AssetId, Rc<()>)>,
+}
+
+fn reload<T: Asset>(bytes: Vec<u8>) -> Result<Box<dyn Any>, GameUtilError> {
+    T::from_bytes(bytes).map(|asset| Box::new(asset) as Box<dyn Any>)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Loads and caches assets from a directory
+///
+/// Tilemaps automatically load their tileset, if a tileset with a matching name isn't already loaded
+/// it's loaded from `<tileset dir>/<name>.ron` or `<tileset dir>/<name>.bin`
+pub struct AssetManager {
+    root: PathBuf,
+    tileset_dir: PathBuf,
+    next_id: u64,
+    entries: HashMap<AssetId, Entry>,
+    paths: HashMap<PathBuf, AssetId>,
+}
+
+impl AssetManager {
+    /// Paths are relative to `root`
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            tileset_dir: PathBuf::new(),
+            next_id: 0,
+            entries: HashMap::new(),
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Directory (relative to root) tilesets are loaded from when resolving tilemaps
+    pub fn set_tileset_dir<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.tileset_dir = dir.into();
+    }
+}
+
+impl AssetManager {
+    /// Load asset at `path`, or return the cached version
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::FileNotFound] or [GameUtilError::ReadingFile] if the file can't be read
+    /// [GameUtilError::AssetType] if the file is already loaded as a different type
+    /// or an error from decoding the asset
+    pub fn load<T: Asset, P: AsRef<Path>>(&mut self, path: P) -> Result<Handle<T>, GameUtilError> {
+        let path = self.root.join(path);
+        self.load_resolved(path)
+    }
+
+    /// Load asset at `path`, which already includes the root
+    fn load_resolved<T: Asset>(&mut self, path: PathBuf) -> Result<Handle<T>, GameUtilError> {
+        if let Some(&id) = self.paths.get(&path) {
+            let entry = self.entries.get_mut(&id).expect("asset path without entry");
+            if !entry.data.is::<T>() {
+                return Err(GameUtilError::AssetType(
+                    path.display().to_string(),
+                    T::NAME,
+                ));
+            }
+            let token = entry.refs.upgrade().unwrap_or_else(|| {
+                let token = Rc::new(());
+                entry.refs = Rc::downgrade(&token);
+                token
+            });
+            return Ok(Handle::new(id, token));
+        }
+
+        let bytes = fs::read(&path).map_err(|e| GameUtilError::from_io(&path, e))?;
+        let asset = T::from_bytes(bytes)?;
+        let id = AssetId(self.next_id);
+        self.next_id += 1;
+        let token = Rc::new(());
+        self.entries.insert(
+            id,
+            Entry {
+                modified: modified(&path),
+                path: path.clone(),
+                data: Box::new(asset),
+                reload: reload::<T>,
+                refs: Rc::downgrade(&token),
+                dependencies: vec![],
+            },
+        );
+        self.paths.insert(path, id);
+        if let Err(e) = self.resolve_dependencies(id) {
+            self.paths.retain(|_, value| *value != id);
+            self.entries.remove(&id);
+            return Err(e);
+        }
+        Ok(Handle::new(id, token))
+    }
+
+    /// Load the tileset for a tilemap
+    fn resolve_dependencies(&mut self, id: AssetId) -> Result<(), GameUtilError> {
+        let Some(name) = self
+            .entries
+            .get(&id)
+            .and_then(|entry| entry.data.downcast_ref::<TilemapFile>())
+            .map(|file| file.tileset.clone())
+        else {
+            return Ok(());
+        };
+        let loaded = self.entries.iter().find_map(|(tileset_id, entry)| {
+            entry
+                .data
+                .downcast_ref::<IciTileset>()
+                .filter(|tileset| tileset.name == name)
+                .map(|_| *tileset_id)
+        });
+        let handle: Handle<IciTileset> = match loaded {
+            Some(tileset_id) => {
+                let path = self.entries[&tileset_id].path.clone();
+                self.load_resolved(path)?
+            }
+            None => {
+                let dir = self.root.join(&self.tileset_dir);
+                let candidates = [
+                    dir.join(format!("{name}.ron")),
+                    dir.join(format!("{name}.bin")),
+                ];
+                match candidates.iter().find(|path| path.is_file()) {
+                    Some(path) => self.load_resolved(path.clone())?,
+                    None => {
+                        return Err(GameUtilError::FileNotFound(format!(
+                            "tileset {name} in {}",
+                            dir.display()
+                        )))
+                    }
+                }
+            }
+        };
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.dependencies = vec![(handle.id, handle.token)];
+        }
+        Ok(())
+    }
+
+    pub fn get<T: Asset>(&self, handle: &Handle<T>) -> Option<&T> {
+        self.entries
+            .get(&handle.id)
+            .and_then(|entry| entry.data.downcast_ref())
+    }
+
+    /// Returns the tileset loaded for `tilemap`
+    pub fn tileset_for(&self, tilemap: &Handle<TilemapFile>) -> Option<Handle<IciTileset>> {
+        let entry = self.entries.get(&tilemap.id)?;
+        entry
+            .dependencies
+            .first()
+            .map(|(id, token)| Handle::new(*id, token.clone()))
+    }
+
+    /// Create a [Tilemap] from `tilemap` and its tileset
+    pub fn build_tilemap(
+        &self,
+        tilemap: &Handle<TilemapFile>,
+        visible_area_px: (u32, u32),
+    ) -> Result<Tilemap<IndexedImage>, GameUtilError> {
+        let missing = || GameUtilError::AssetType(format!("{:?}", tilemap.id), TilemapFile::NAME);
+        let file = self.get(tilemap).ok_or_else(missing)?;
+        let tileset = self
+            .tileset_for(tilemap)
+            .and_then(|handle| self.get(&handle).cloned())
+            .ok_or_else(|| GameUtilError::FileNotFound(format!("tileset {}", file.tileset)))?
+            .into_tileset()
+            .map_err(GameUtilError::InvalidImage)?;
+        file.clone().into_tilemap(&tileset, visible_area_px)
+    }
+
+    pub fn path_of(&self, id: AssetId) -> Option<&Path> {
+        self.entries.get(&id).map(|entry| entry.path.as_path())
+    }
+
+    #[inline]
+    pub fn is_loaded(&self, id: AssetId) -> bool {
+        self.entries.contains_key(&id)
+    }
+
+    /// Number of loaded assets
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl AssetManager {
+    /// Reload any assets whose files have changed since they were loaded
+    ///
+    /// Hot reloading is done by polling, not by watching the filesystem: nothing is reloaded
+    /// until this is called, and it checks the modified time of every loaded file.
+    /// The caller must call it periodically (e.g. once a second) rather than every frame
+    pub fn poll_changes(&mut self) -> Vec<AssetEvent> {
+        let mut events = vec![];
+        let mut changed = vec![];
+        let mut ids: Vec<AssetId> = self.entries.keys().copied().collect();
+        ids.sort();
+        for id in ids {
+            let entry = self.entries.get_mut(&id).expect("missing entry");
+            let current = modified(&entry.path);
+            if current.is_none() || current == entry.modified {
+                continue;
+            }
+            entry.modified = current;
+            let result = fs::read(&entry.path)
+                .map_err(|e| GameUtilError::from_io(&entry.path, e))
+                .and_then(entry.reload);
+            match result {
+                Ok(data) => {
+                    let old = std::mem::replace(&mut entry.data, data);
+                    if let Err(e) = self.resolve_dependencies(id) {
+                        self.entries.get_mut(&id).expect("missing entry").data = old;
+                        events.push(AssetEvent::ReloadFailed(id, e.to_string()));
+                    } else {
+                        events.push(AssetEvent::Reloaded(id));
+                        changed.push(id);
+                    }
+                }
+                Err(e) => events.push(AssetEvent::ReloadFailed(id, e.to_string())),
+            }
+        }
+        for dependency in changed {
+            let mut dependents: Vec<AssetId> = self
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.dependencies.iter().any(|(id, _)| *id == dependency))
+                .map(|(id, _)| *id)
+                .collect();
+            dependents.sort();
+            for asset in dependents {
+                events.push(AssetEvent::DependencyChanged { asset, dependency });
+            }
+        }
+        events
+    }
+
+    /// Unload all assets without any handles, returns the ids of unloaded assets
+    pub fn remove_unused(&mut self) -> Vec<AssetId> {
+        let mut removed = vec![];
+        loop {
+            let unused: Vec<AssetId> = self
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.refs.strong_count() == 0)
+                .map(|(id, _)| *id)
+                .collect();
+            if unused.is_empty() {
+                break;
+            }
+            for id in unused {
+                self.entries.remove(&id);
+                removed.push(id);
+            }
+        }
+        self.paths.retain(|_, id| self.entries.contains_key(id));
+        removed.sort();
+        removed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+    use std::path::Component;
+    use std::time::Duration;
+
+    const TILESET: &str = r#"(
+        name: "desert",
+        tile_size: (1,1),
+        palette: [4278190335],
+        tiles: { "sand": [0] },
+    )"#;
+
+    const TILEMAP: &str = r#"(
+        name: "temple",
+        tileset: "desert",
+        flags: {},
+        tiles: [(image: "sand")],
+        map: [[0,0],[0,0]],
+        data: (start: (0,0), exits: []),
+    )"#;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sgu_assets_{name}_{}", std::process::id()));
+        write_files(dir)
+    }
+
+    fn write_files(dir: PathBuf) -> PathBuf {
+        fs::create_dir_all(dir.join("tilesets")).unwrap();
+        fs::write(dir.join("tilesets/desert.ron"), TILESET).unwrap();
+        fs::write(dir.join("temple.ron"), TILEMAP).unwrap();
+        dir
+    }
+
+    fn touch(path: &Path, contents: &str) {
+        fs::write(path, contents).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+    }
+
+    #[test]
+    fn loading_and_caching() {
+        let dir = dir("loading");
+        let mut assets = AssetManager::new(&dir);
+        assets.set_tileset_dir("tilesets");
+        let map: Handle<TilemapFile> = assets.load("temple.ron").unwrap();
+        assert_eq!(assets.len(), 2);
+        let tileset = assets.tileset_for(&map).unwrap();
+        assert_eq!(assets.get(&tileset).unwrap().name, "desert");
+        let again: Handle<TilemapFile> = assets.load("temple.ron").unwrap();
+        assert_eq!(again, map);
+        assert!(matches!(
+            assets.load::<IciTileset, _>("temple.ron"),
+            Err(GameUtilError::AssetType(_, "tileset"))
+        ));
+        assert!(matches!(
+            assets.load::<SoundAsset, _>("missing.wav"),
+            Err(GameUtilError::FileNotFound(_))
+        ));
+        let tilemap = assets.build_tilemap(&map, (2, 2)).unwrap();
+        assert_eq!(tilemap.size(), MapSize::new(2, 2));
+
+        drop(tileset);
+        assert!(assets.remove_unused().is_empty());
+        drop(map);
+        assert_eq!(assets.remove_unused().len(), 0);
+        drop(again);
+        assert_eq!(assets.remove_unused().len(), 2);
+        assert!(assets.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn relative_root() {
+        let abs_dir = dir("relative");
+        //same temp dir, reached with `..` from the dir tests run in
+        let cwd = std::env::current_dir().unwrap();
+        let up = cwd
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .map(|_| Component::ParentDir);
+        let down = abs_dir
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)));
+        let dir: PathBuf = up.chain(down).collect();
+        assert!(dir.is_relative());
+        let mut assets = AssetManager::new(&dir);
+        assets.set_tileset_dir("tilesets");
+        let map: Handle<TilemapFile> = assets.load("temple.ron").unwrap();
+        let tileset = assets.tileset_for(&map).unwrap();
+        assert_eq!(assets.len(), 2);
+        //tileset already loaded
+        let mut assets = AssetManager::new(&dir);
+        assets.set_tileset_dir("tilesets");
+        let loaded: Handle<IciTileset> = assets.load("tilesets/desert.ron").unwrap();
+        let map: Handle<TilemapFile> = assets.load("temple.ron").unwrap();
+        assert_eq!(assets.tileset_for(&map), Some(loaded));
+        assert_eq!(assets.len(), 2);
+        drop(tileset);
+        fs::remove_dir_all(abs_dir).unwrap();
+    }
+
+    #[test]
+    fn hot_reload() {
+        let dir = dir("reload");
+        let mut assets = AssetManager::new(&dir);
+        assets.set_tileset_dir("tilesets");
+        let map: Handle<TilemapFile> = assets.load("temple.ron").unwrap();
+        let tileset = assets.tileset_for(&map).unwrap();
+        assert!(assets.poll_changes().is_empty());
+
+        touch(
+            &dir.join("tilesets/desert.ron"),
+            &TILESET.replace("4278190335", "16711935"),
+        );
+        let events = assets.poll_changes();
+        assert_eq!(
+            events,
+            vec![
+                AssetEvent::Reloaded(tileset.id()),
+                AssetEvent::DependencyChanged {
+                    asset: map.id(),
+                    dependency: tileset.id()
+                }
+            ]
+        );
+        assert!(events.iter().any(|event| event.affects(map.id())));
+        assert_eq!(
+            assets.get(&tileset).unwrap().palette(),
+            &vec![Color::new(0, 255, 0, 255)]
+        );
+
+        touch(&dir.join("temple.ron"), "not ron");
+        let events = assets.poll_changes();
+        assert!(matches!(events[..], [AssetEvent::ReloadFailed(id, _)] if id == map.id()));
+        assert_eq!(assets.get(&map).unwrap().name, "temple");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
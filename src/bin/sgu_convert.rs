@@ -0,0 +1,90 @@
+//! Converts tilemaps and tilesets between RON and binary
+//!
+//! Usage: `sgu-convert <input> <output> [--compress]`
+//!
+//! Outputs ending with `.ron` are written as RON, everything else is written as binary
+
+use std::fs;
+use std::path::Path;
+use std::process::exit;
+
+use ron::ser::PrettyConfig;
+use simple_game_utils::prelude::*;
+
+enum Asset {
+    Tilemap(TilemapFile),
+    Tileset(IciTileset),
+}
+
+fn read(path: &str) -> Result<Asset, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    if has_magic(&bytes, TILEMAP_MAGIC) {
+        return TilemapFile::from_binary(&bytes)
+            .map(Asset::Tilemap)
+            .map_err(|e| e.to_string());
+    }
+    if has_magic(&bytes, TILESET_MAGIC) {
+        return IciTileset::from_binary(&bytes)
+            .map(Asset::Tileset)
+            .map_err(|e| e.to_string());
+    }
+    let text = String::from_utf8(bytes).map_err(|_| format!("{path} is not RON or binary"))?;
+    match ron::from_str::<TilemapFile>(&text) {
+        Ok(tilemap) => Ok(Asset::Tilemap(tilemap)),
+        Err(map_err) => ron::from_str::<IciTileset>(&text)
+            .map(Asset::Tileset)
+            .map_err(|set_err| {
+                format!("{path} is not a tilemap ({map_err}) or tileset ({set_err})")
+            }),
+    }
+}
+
+fn write(path: &str, asset: &Asset, compression: BinaryCompression) -> Result<(), String> {
+    let is_ron = Path::new(path)
+        .extension()
+        .map(|ext| ext == "ron")
+        .unwrap_or(false);
+    let bytes = if is_ron {
+        let config = PrettyConfig::default();
+        match asset {
+            Asset::Tilemap(tilemap) => ron::ser::to_string_pretty(tilemap, config),
+            Asset::Tileset(tileset) => ron::ser::to_string_pretty(tileset, config),
+        }
+        .map_err(|e| e.to_string())?
+        .into_bytes()
+    } else {
+        match asset {
+            Asset::Tilemap(tilemap) => tilemap.to_binary(compression),
+            Asset::Tileset(tileset) => tileset.to_binary(compression),
+        }
+        .map_err(|e| e.to_string())?
+    };
+    fs::write(path, bytes).map_err(|e| format!("Unable to write {path}: {e}"))
+}
+
+fn compression(compress: bool) -> Result<BinaryCompression, String> {
+    if !compress {
+        return Ok(BinaryCompression::None);
+    }
+    #[cfg(feature = "compression")]
+    return Ok(BinaryCompression::Deflate);
+    #[cfg(not(feature = "compression"))]
+    return Err(String::from("--compress requires the compression feature"));
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let compress = args.iter().any(|arg| arg == "--compress");
+    let paths: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    if paths.len() != 2 {
+        eprintln!("Usage: sgu-convert <input> <output> [--compress]");
+        exit(1);
+    }
+    let result = compression(compress)
+        .and_then(|compression| read(paths[0]).map(|asset| (asset, compression)))
+        .and_then(|(asset, compression)| write(paths[1], &asset, compression));
+    if let Err(e) = result {
+        eprintln!("{e}");
+        exit(1);
+    }
+}
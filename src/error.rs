@@ -1,3 +1,7 @@
+#[cfg(any(feature = "ici", feature = "sound", feature = "assets"))]
+use std::io::ErrorKind;
+#[cfg(any(feature = "ici", feature = "sound", feature = "assets"))]
+use std::path::Path;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,8 +10,23 @@ pub enum GameUtilError {
     #[error("Error init'ing sound effect: {0}")]
     SoundEffectInit(&'static str),
     #[cfg(feature = "sound")]
-    #[error("Invalid sound data")]
+    #[error("Invalid sound data: {0}")]
     SoundEffectInvalid(hound::Error),
+    #[cfg(feature = "sound")]
+    #[error("Unsupported sound: {0}")]
+    UnsupportedSound(String),
+    #[cfg(feature = "sound")]
+    #[error("Invalid sound data: {0}")]
+    SoundDecode(String),
+    #[cfg(feature = "sound")]
+    #[error("No sound named {0}")]
+    UnknownSound(String),
+    #[cfg(feature = "sound")]
+    #[error("Invalid song: {0}")]
+    InvalidSong(String),
+    #[cfg(feature = "sound")]
+    #[error("Writing sound: {0}")]
+    WritingSound(String),
     #[cfg(feature = "prefs")]
     #[error("Unable to get app pref dir")]
     AppPrefDir,
@@ -20,7 +39,7 @@ pub enum GameUtilError {
     #[cfg(feature = "prefs")]
     #[error("Loading prefs: {0} from {1}")]
     Loading(String, String),
-    #[cfg(feature = "prefs")]
+    #[cfg(any(feature = "prefs", feature = "assets"))]
     #[error("Deserializing data: {0}")]
     Deserializing(String),
     #[cfg(feature = "prefs")]
@@ -28,4 +47,40 @@ pub enum GameUtilError {
     MakingDirs(String, String),
     #[error("Tileset for {0} is missing images: {1:?}")]
     InvalidTileset(String, Vec<String>),
+    #[error("Can't merge tilesets: {0}")]
+    TilesetMerge(String),
+    #[error("Invalid binary data: {0}")]
+    InvalidBinary(String),
+    #[error("Invalid atlas: {0}")]
+    InvalidAtlas(String),
+    #[cfg(feature = "assets")]
+    #[error("Asset {0} is not a {1}")]
+    AssetType(String, &'static str),
+    #[error("File not found: {0}")]
+    FileNotFound(String),
+    #[error("Reading file {0}: {1}")]
+    ReadingFile(String, String),
+    #[cfg(feature = "ici")]
+    #[error("Image would be {0}x{1}px, max is 255x255")]
+    ImageTooLarge(usize, usize),
+    #[cfg(feature = "ici")]
+    #[error("Image would need {0} colors, max is 256")]
+    TooManyColors(usize),
+    #[cfg(feature = "ici")]
+    #[error("Invalid image: {0}")]
+    InvalidImage(String),
+    #[cfg(feature = "ici")]
+    #[error("Invalid palette: {0}")]
+    InvalidPalette(String),
+}
+
+#[cfg(any(feature = "ici", feature = "sound", feature = "assets"))]
+impl GameUtilError {
+    pub(crate) fn from_io(path: &Path, err: std::io::Error) -> Self {
+        if err.kind() == ErrorKind::NotFound {
+            GameUtilError::FileNotFound(path.display().to_string())
+        } else {
+            GameUtilError::ReadingFile(path.display().to_string(), err.to_string())
+        }
+    }
 }
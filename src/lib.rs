@@ -36,17 +36,23 @@
 //!# }
 //! ```
 
+#[cfg(feature = "assets")]
+pub mod assets;
 #[cfg(feature = "controller")]
 pub mod controller;
 pub mod error;
 #[cfg(feature = "prefs")]
 pub mod prefs;
 #[cfg(feature = "sound")]
+pub mod sound;
+#[cfg(feature = "sound")]
 pub mod sound_effect;
 pub mod tiles;
 pub mod timing;
 
 pub mod prelude {
+    #[cfg(feature = "assets")]
+    pub use crate::assets::*;
     #[cfg(feature = "controller")]
     pub use crate::controller::*;
     pub use crate::error::*;
@@ -55,6 +61,8 @@ pub mod prelude {
     #[cfg(feature = "prefs")]
     pub use crate::prefs::*;
     #[cfg(feature = "sound")]
+    pub use crate::sound::prelude::*;
+    #[cfg(feature = "sound")]
     pub use crate::sound_effect::*;
     pub use crate::tiles::prelude::*;
     pub use crate::timing::*;
@@ -0,0 +1,421 @@
+use crate::error::GameUtilError;
+use audio_engine::converter::{ChannelConverter, SampleRateConverter};
+use audio_engine::{AudioEngine, Mixer, SampleRate, Sound, SoundSource};
+use std::fmt::{Debug, Formatter};
+use std::io::{Seek, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Somewhere sounds are played, either a device with [AudioEngine] or memory with [OfflineBackend]
+///
+/// [NewSoundEffect](crate::sound_effect::NewSoundEffect), [NewMusicTrack](crate::sound::music::NewMusicTrack)
+/// and [SoundManager](crate::sound::manager::SoundManager) work with any backend
+pub trait AudioBackend {
+    /// Add a stopped sound, it's converted to the output channels and sample rate
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::SoundEffectInit] if the sound can't be created
+    fn add_sound(&self, source: Box<dyn SoundSource + Send>) -> Result<SoundHandle, GameUtilError>;
+
+    /// Output sample rate
+    fn sample_rate(&self) -> u32;
+
+    /// Output channels
+    fn channels(&self) -> u16;
+}
+
+impl AudioBackend for AudioEngine {
+    fn add_sound(&self, source: Box<dyn SoundSource + Send>) -> Result<SoundHandle, GameUtilError> {
+        self.new_sound(source)
+            .map(SoundHandle::from)
+            .map_err(GameUtilError::SoundEffectInit)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        AudioEngine::sample_rate(self)
+    }
+
+    fn channels(&self) -> u16 {
+        AudioEngine::channels(self)
+    }
+}
+
+/// A sound added to an [AudioBackend], it's removed from the backend when this is dropped and the sound ends
+pub struct SoundHandle {
+    inner: HandleInner,
+}
+
+enum HandleInner {
+    Engine(Sound),
+    Offline { mixer: Arc<Mutex<Mixer>>, id: u64 },
+}
+
+impl Debug for SoundHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.inner {
+            HandleInner::Engine(_) => write!(f, "SoundHandle: engine"),
+            HandleInner::Offline { id, .. } => write!(f, "SoundHandle: offline {id}"),
+        }
+    }
+}
+
+impl From<Sound> for SoundHandle {
+    fn from(sound: Sound) -> Self {
+        Self {
+            inner: HandleInner::Engine(sound),
+        }
+    }
+}
+
+fn lock(mixer: &Mutex<Mixer>) -> MutexGuard<'_, Mixer> {
+    mixer.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl SoundHandle {
+    pub fn play(&mut self) {
+        match &mut self.inner {
+            HandleInner::Engine(sound) => sound.play(),
+            HandleInner::Offline { mixer, id } => lock(mixer).play(*id),
+        }
+    }
+
+    pub fn pause(&mut self) {
+        match &mut self.inner {
+            HandleInner::Engine(sound) => sound.pause(),
+            HandleInner::Offline { mixer, id } => lock(mixer).pause(*id),
+        }
+    }
+
+    /// Pause and reset to the start
+    pub fn stop(&mut self) {
+        match &mut self.inner {
+            HandleInner::Engine(sound) => sound.stop(),
+            HandleInner::Offline { mixer, id } => lock(mixer).stop(*id),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        match &mut self.inner {
+            HandleInner::Engine(sound) => sound.reset(),
+            HandleInner::Offline { mixer, id } => lock(mixer).reset(*id),
+        }
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        match &mut self.inner {
+            HandleInner::Engine(sound) => sound.set_volume(volume),
+            HandleInner::Offline { mixer, id } => lock(mixer).set_volume(*id, volume),
+        }
+    }
+
+    pub fn set_loop(&mut self, loops: bool) {
+        match &mut self.inner {
+            HandleInner::Engine(sound) => sound.set_loop(loops),
+            HandleInner::Offline { mixer, id } => lock(mixer).set_loop(*id, loops),
+        }
+    }
+}
+
+impl Drop for SoundHandle {
+    fn drop(&mut self) {
+        if let HandleInner::Offline { mixer, id } = &self.inner {
+            lock(mixer).mark_to_remove(*id, true);
+        }
+    }
+}
+
+/// Mixes sounds into memory instead of playing them, for tests and machines without audio devices
+///
+/// Nothing plays until [OfflineBackend::render] is called
+///
+/// # Usage
+///
+/// ```
+///# use simple_game_utils::prelude::*;
+/// let mut backend = OfflineBackend::new(2, 44100);
+/// let mut blip = backend.load_from_synth(&SynthParams::preset(SynthPreset::Blip, 1)).unwrap();
+/// blip.play();
+/// backend.render(0.5);
+/// assert!(backend.output().iter().any(|&s| s != 0));
+/// ```
+pub struct OfflineBackend {
+    mixer: Arc<Mutex<Mixer>>,
+    channels: u16,
+    sample_rate: u32,
+    output: Vec<i16>,
+}
+
+impl Debug for OfflineBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "OfflineBackend: channels: {}, sample_rate: {}, sounds: {}, output: {:.2}s",
+            self.channels,
+            self.sample_rate,
+            self.sound_count(),
+            self.output_duration()
+        )
+    }
+}
+
+impl Default for OfflineBackend {
+    /// Stereo at 44.1kHz
+    fn default() -> Self {
+        Self::new(2, 44100)
+    }
+}
+
+impl OfflineBackend {
+    /// `channels` and `sample_rate` must be more than 0, they're raised to 1 otherwise
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let channels = channels.max(1);
+        let sample_rate = sample_rate.max(1);
+        Self {
+            mixer: Arc::new(Mutex::new(Mixer::new(channels, SampleRate(sample_rate)))),
+            channels,
+            sample_rate,
+            output: vec![],
+        }
+    }
+
+    /// Mix `seconds` of audio, rounded to the nearest frame, and append it to [OfflineBackend::output]
+    ///
+    /// Returns the new samples
+    pub fn render(&mut self, seconds: f64) -> &[i16] {
+        let frames = (seconds.max(0.0) * self.sample_rate as f64).round() as usize;
+        self.render_frames(frames)
+    }
+
+    /// Mix `frames` of audio and append it to [OfflineBackend::output]
+    ///
+    /// Returns the new samples
+    pub fn render_frames(&mut self, frames: usize) -> &[i16] {
+        let start = self.output.len();
+        self.output
+            .resize(start + frames * self.channels as usize, 0);
+        lock(&self.mixer).write_samples(&mut self.output[start..]);
+        &self.output[start..]
+    }
+
+    /// Interleaved samples mixed so far
+    #[inline]
+    pub fn output(&self) -> &[i16] {
+        &self.output
+    }
+
+    /// Length of [OfflineBackend::output] in seconds
+    pub fn output_duration(&self) -> f64 {
+        self.output.len() as f64 / self.channels as f64 / self.sample_rate as f64
+    }
+
+    pub fn take_output(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.output)
+    }
+
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+
+    /// Number of sounds in the mixer, including stopped sounds that haven't been dropped
+    pub fn sound_count(&self) -> usize {
+        lock(&self.mixer).sound_count()
+    }
+
+    /// Number of sounds playing
+    pub fn playing_count(&self) -> usize {
+        lock(&self.mixer).playing_count()
+    }
+
+    /// Write [OfflineBackend::output] as a 16 bit WAV file
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::WritingSound] if the file can't be written
+    pub fn write_wav<P: AsRef<Path>>(&self, path: P) -> Result<(), GameUtilError> {
+        let writer = hound::WavWriter::create(path, self.wav_spec())
+            .map_err(|e| GameUtilError::WritingSound(e.to_string()))?;
+        self.write_samples(writer)
+    }
+
+    /// Write [OfflineBackend::output] as a 16 bit WAV to `writer`
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::WritingSound] if the data can't be written
+    pub fn write_wav_to<W: Write + Seek>(&self, writer: W) -> Result<(), GameUtilError> {
+        let writer = hound::WavWriter::new(writer, self.wav_spec())
+            .map_err(|e| GameUtilError::WritingSound(e.to_string()))?;
+        self.write_samples(writer)
+    }
+
+    fn wav_spec(&self) -> hound::WavSpec {
+        hound::WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        }
+    }
+
+    fn write_samples<W: Write + Seek>(
+        &self,
+        mut writer: hound::WavWriter<W>,
+    ) -> Result<(), GameUtilError> {
+        let mut samples = writer.get_i16_writer(self.output.len() as u32);
+        for &sample in &self.output {
+            samples.write_sample(sample);
+        }
+        samples
+            .flush()
+            .and_then(|_| writer.finalize())
+            .map_err(|e| GameUtilError::WritingSound(e.to_string()))
+    }
+}
+
+impl AudioBackend for OfflineBackend {
+    fn add_sound(&self, source: Box<dyn SoundSource + Send>) -> Result<SoundHandle, GameUtilError> {
+        let source: Box<dyn SoundSource + Send> = if source.sample_rate() == 0 {
+            return Err(GameUtilError::SoundEffectInit(
+                "sample rate must be more than 0",
+            ));
+        } else if source.sample_rate() != self.sample_rate {
+            let source = SampleRateConverter::new(source, self.sample_rate);
+            if source.channels() == self.channels {
+                Box::new(source)
+            } else {
+                Box::new(ChannelConverter::new(source, self.channels))
+            }
+        } else if source.channels() != self.channels {
+            Box::new(ChannelConverter::new(source, self.channels))
+        } else {
+            source
+        };
+        let mut mixer = lock(&self.mixer);
+        let id = mixer.add_sound((), source);
+        mixer.mark_to_remove(id, false);
+        Ok(SoundHandle {
+            inner: HandleInner::Offline {
+                mixer: self.mixer.clone(),
+                id,
+            },
+        })
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sound::fade::{Easing, Fadeable};
+    use crate::sound::manager::{SoundGroup, SoundManager, SoundSettings};
+    use crate::sound_effect::NewSoundEffect;
+    use std::io::Cursor;
+
+    //stereo at 10Hz so frames are easy to count
+    fn backend() -> OfflineBackend {
+        OfflineBackend::new(2, 10)
+    }
+
+    fn left(samples: &[i16]) -> Vec<i16> {
+        samples.iter().step_by(2).copied().collect()
+    }
+
+    #[test]
+    fn playback() {
+        let mut backend = backend();
+        let mut sound = backend.load_from_pcm(vec![1000; 10], 1, 10).unwrap();
+        assert_eq!(sound.duration(), 1.0);
+        assert_eq!(backend.render_frames(2), &[0; 4]);
+        sound.play();
+        assert_eq!(backend.render_frames(5), &[1000; 10]);
+        sound.set_volume(0.5);
+        assert_eq!(backend.render_frames(2), &[500; 4]);
+        sound.reset();
+        assert_eq!(backend.render_frames(2), &[0; 4]);
+        sound.set_volume(1.0);
+        sound.play();
+        let output = left(backend.render_frames(12));
+        assert_eq!(&output[..10], &[1000; 10]);
+        assert_eq!(&output[10..], &[0; 2]);
+        assert_eq!(backend.output_duration(), 2.3);
+
+        sound.update_secs(1.1);
+        sound.update_secs(0.0);
+        assert!(!sound.is_playing());
+        sound.set_loop(true);
+        sound.set_pan(-1.0);
+        sound.play();
+        let output = backend.render_frames(25);
+        assert_eq!(left(output), vec![1000; 25]);
+        assert!(output.iter().skip(1).step_by(2).all(|&s| s == 0));
+    }
+
+    #[test]
+    fn fading_and_freeing() {
+        let mut backend = backend();
+        let mut sound = backend.load_from_pcm(vec![1000; 100], 1, 10).unwrap();
+        sound.play();
+        sound.fade_out(1.0, Easing::Linear);
+        sound.update_secs(0.5);
+        assert_eq!(backend.render_frames(1), &[500; 2]);
+        sound.update_secs(0.5);
+        assert!(!sound.is_playing());
+        assert_eq!(backend.render_frames(1), &[0; 2]);
+        assert_eq!(backend.sound_count(), 1);
+        drop(sound);
+        backend.render_frames(1);
+        assert_eq!(backend.sound_count(), 0);
+    }
+
+    #[test]
+    fn wav_and_manager() {
+        let mut backend = backend();
+        let mut sound = backend.load_from_pcm(vec![1000; 5], 1, 10).unwrap();
+        sound.play();
+        backend.render_frames(5);
+        let mut wav = Cursor::new(vec![]);
+        backend.write_wav_to(&mut wav).unwrap();
+        let reader = hound::WavReader::new(Cursor::new(wav.get_ref().clone())).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 10);
+        let samples: Vec<i16> = reader.into_samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![1000; 10]);
+        drop(sound);
+        let from_vec = backend.load_from_vec(wav.get_ref().clone()).unwrap();
+        assert_eq!(from_vec.duration(), 0.5);
+        let bytes: &'static [u8] = Box::leak(wav.get_ref().clone().into_boxed_slice());
+        let from_bytes = backend.load_from_bytes(bytes, 2.0).unwrap();
+        assert_eq!(from_bytes.duration(), 2.0);
+        drop((from_vec, from_bytes));
+
+        let mut sounds = SoundManager::new(backend);
+        sounds
+            .add("beep", SoundGroup::Sfx, wav.into_inner())
+            .unwrap();
+        sounds.set_settings(
+            "beep",
+            SoundSettings {
+                max_voices: 2,
+                ..SoundSettings::default()
+            },
+        );
+        sounds.engine_mut().clear_output();
+        sounds.play("beep").unwrap();
+        sounds.play("beep").unwrap();
+        assert_eq!(sounds.voices("beep"), 2);
+        let output = left(sounds.engine_mut().render_frames(7));
+        assert_eq!(output, [2000, 2000, 2000, 2000, 2000, 0, 0]);
+        sounds.update_secs(0.7);
+        assert_eq!(sounds.voices("beep"), 0);
+        sounds.engine_mut().render_frames(1);
+        assert_eq!(sounds.engine().sound_count(), 0);
+    }
+}
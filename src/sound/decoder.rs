@@ -0,0 +1,810 @@
+//! Decoding for WAV, Ogg Vorbis, FLAC and MP3
+//!
+//! WAV is always supported, the others require the `ogg`, `flac` and `mp3` features
+
+use crate::error::GameUtilError;
+#[cfg(feature = "ogg")]
+use audio_engine::OggDecoder;
+use audio_engine::{SoundSource, WavDecoder};
+use hound::WavReader;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+#[cfg(any(feature = "flac", feature = "mp3"))]
+use std::vec::IntoIter;
+
+/// Audio file format
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum SoundFormat {
+    Wav,
+    Ogg,
+    Flac,
+    Mp3,
+}
+
+impl SoundFormat {
+    /// Detect the format from the start of a file, 12 bytes is enough
+    pub fn detect(bytes: &[u8]) -> Option<SoundFormat> {
+        match bytes {
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => {
+                Some(SoundFormat::Wav)
+            }
+            [b'O', b'g', b'g', b'S', ..] => Some(SoundFormat::Ogg),
+            [b'f', b'L', b'a', b'C', ..] => Some(SoundFormat::Flac),
+            [b'I', b'D', b'3', ..] => Some(SoundFormat::Mp3),
+            [0xFF, sync, ..] if sync & 0xE0 == 0xE0 => Some(SoundFormat::Mp3),
+            _ => None,
+        }
+    }
+
+    /// Returns false if the feature for this format isn't enabled
+    pub fn is_supported(&self) -> bool {
+        match self {
+            SoundFormat::Wav => true,
+            SoundFormat::Ogg => cfg!(feature = "ogg"),
+            SoundFormat::Flac => cfg!(feature = "flac"),
+            SoundFormat::Mp3 => cfg!(feature = "mp3"),
+        }
+    }
+}
+
+impl Display for SoundFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SoundFormat::Wav => "wav",
+            SoundFormat::Ogg => "ogg",
+            SoundFormat::Flac => "flac",
+            SoundFormat::Mp3 => "mp3",
+        };
+        write!(f, "{name}")
+    }
+}
+
+enum Source<R: Read + Seek + Send + 'static> {
+    Wav(WavDecoder<R>),
+    #[cfg(feature = "ogg")]
+    Ogg(Box<OggDecoder<R>>),
+    #[cfg(feature = "flac")]
+    Flac(FlacDecoder<R>),
+    #[cfg(feature = "mp3")]
+    Mp3(Mp3Decoder<R>),
+}
+
+/// Streams audio from any supported format
+///
+/// The format is detected from the data, not the file name
+pub struct SoundDecoder<R: Read + Seek + Send + 'static> {
+    source: Source<R>,
+    format: SoundFormat,
+    //Length in seconds
+    duration: f64,
+}
+
+impl<R: Read + Seek + Send + 'static> SoundDecoder<R> {
+    /// # Errors
+    ///
+    /// * [GameUtilError::UnsupportedSound] if the format is unknown or its feature isn't enabled
+    /// * [GameUtilError::SoundEffectInvalid] if WAV data is invalid
+    /// * [GameUtilError::SoundDecode] if other data is invalid
+    pub fn new(reader: R) -> Result<Self, GameUtilError> {
+        Self::open(reader, true)
+    }
+
+    /// Like [SoundDecoder::new] but skips finding the length, which scans all of Ogg and MP3 data
+    ///
+    /// [SoundDecoder::duration] is always 0.0
+    pub(crate) fn without_duration(reader: R) -> Result<Self, GameUtilError> {
+        Self::open(reader, false)
+    }
+
+    fn open(mut reader: R, probe_duration: bool) -> Result<Self, GameUtilError> {
+        let header = read_header(&mut reader)
+            .map_err(|e| GameUtilError::SoundDecode(format!("reading header: {e}")))?;
+        let format = SoundFormat::detect(&header)
+            .ok_or_else(|| GameUtilError::UnsupportedSound(String::from("unknown format")))?;
+        let (source, duration) = match format {
+            SoundFormat::Wav => {
+                let duration = if probe_duration {
+                    wav_duration(&mut reader)?
+                } else {
+                    0.0
+                };
+                let decoder = WavDecoder::new(reader).map_err(GameUtilError::SoundEffectInvalid)?;
+                (Source::Wav(decoder), duration)
+            }
+            #[cfg(feature = "ogg")]
+            SoundFormat::Ogg => {
+                let duration = if probe_duration {
+                    ogg_duration(&mut reader).map_err(decode_err(format))?
+                } else {
+                    0.0
+                };
+                let decoder = OggDecoder::new(reader).map_err(decode_err(format))?;
+                (Source::Ogg(Box::new(decoder)), duration)
+            }
+            #[cfg(feature = "flac")]
+            SoundFormat::Flac => {
+                let decoder = FlacDecoder::new(reader).map_err(decode_err(format))?;
+                let duration = decoder.duration();
+                (Source::Flac(decoder), duration)
+            }
+            #[cfg(feature = "mp3")]
+            SoundFormat::Mp3 => {
+                let duration = if probe_duration {
+                    mp3_duration(&mut reader).map_err(decode_err(format))?
+                } else {
+                    0.0
+                };
+                let decoder = Mp3Decoder::new(reader).map_err(decode_err(format))?;
+                (Source::Mp3(decoder), duration)
+            }
+            #[cfg(not(feature = "ogg"))]
+            SoundFormat::Ogg => return Err(unsupported(format)),
+            #[cfg(not(feature = "flac"))]
+            SoundFormat::Flac => return Err(unsupported(format)),
+            #[cfg(not(feature = "mp3"))]
+            SoundFormat::Mp3 => return Err(unsupported(format)),
+        };
+        Ok(Self {
+            source,
+            format,
+            duration,
+        })
+    }
+
+    #[inline]
+    pub fn format(&self) -> SoundFormat {
+        self.format
+    }
+
+    /// Length in seconds
+    #[inline]
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    fn source(&self) -> &dyn SoundSource {
+        match &self.source {
+            Source::Wav(decoder) => decoder,
+            #[cfg(feature = "ogg")]
+            Source::Ogg(decoder) => decoder,
+            #[cfg(feature = "flac")]
+            Source::Flac(decoder) => decoder,
+            #[cfg(feature = "mp3")]
+            Source::Mp3(decoder) => decoder,
+        }
+    }
+
+    fn source_mut(&mut self) -> &mut dyn SoundSource {
+        match &mut self.source {
+            Source::Wav(decoder) => decoder,
+            #[cfg(feature = "ogg")]
+            Source::Ogg(decoder) => decoder,
+            #[cfg(feature = "flac")]
+            Source::Flac(decoder) => decoder,
+            #[cfg(feature = "mp3")]
+            Source::Mp3(decoder) => decoder,
+        }
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> SoundSource for SoundDecoder<R> {
+    fn channels(&self) -> u16 {
+        self.source().channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source().sample_rate()
+    }
+
+    fn reset(&mut self) {
+        self.source_mut().reset()
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        self.source_mut().write_samples(buffer)
+    }
+}
+
+pub(crate) fn open_file(path: &Path) -> Result<BufReader<File>, GameUtilError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| GameUtilError::from_io(path, e))
+}
+
+#[cfg(not(all(feature = "ogg", feature = "flac", feature = "mp3")))]
+fn unsupported(format: SoundFormat) -> GameUtilError {
+    GameUtilError::UnsupportedSound(format!("{format} requires the {format} feature"))
+}
+
+#[cfg(any(feature = "ogg", feature = "flac", feature = "mp3"))]
+fn decode_err<E: Display>(format: SoundFormat) -> impl Fn(E) -> GameUtilError {
+    move |e| GameUtilError::SoundDecode(format!("{format}: {e}"))
+}
+
+/// Reads the first 12 bytes, leaving `reader` where it started
+fn read_header<R: Read + Seek>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+    let start = reader.stream_position()?;
+    let mut header = Vec::with_capacity(12);
+    reader.by_ref().take(12).read_to_end(&mut header)?;
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(header)
+}
+
+/// Reads the length in seconds from the WAV header, leaving `reader` where it started
+fn wav_duration<R: Read + Seek>(reader: &mut R) -> Result<f64, GameUtilError> {
+    let start = reader
+        .stream_position()
+        .map_err(|e| GameUtilError::SoundEffectInvalid(hound::Error::IoError(e)))?;
+    let (sample_rate, samples) = WavReader::new(&mut *reader)
+        .map(|wav| (wav.spec().sample_rate, wav.duration()))
+        .map_err(GameUtilError::SoundEffectInvalid)?;
+    reader
+        .seek(SeekFrom::Start(start))
+        .map_err(|e| GameUtilError::SoundEffectInvalid(hound::Error::IoError(e)))?;
+    if sample_rate == 0 {
+        return Err(GameUtilError::SoundEffectInvalid(
+            hound::Error::FormatError("sample rate is 0"),
+        ));
+    }
+    Ok(samples as f64 / sample_rate as f64)
+}
+
+/// Reads the sample rate from the identification header and the
+/// sample count from the granule position of the last page
+#[cfg(feature = "ogg")]
+fn ogg_duration<R: Read + Seek>(reader: &mut R) -> Result<f64, String> {
+    const SCAN: u64 = 65536;
+    let start = reader.stream_position().map_err(|e| e.to_string())?;
+    let mut bytes = vec![];
+    reader
+        .by_ref()
+        .take(512)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    let ident = find(&bytes, b"\x01vorbis")
+        .filter(|i| i + 16 <= bytes.len())
+        .ok_or_else(|| String::from("missing identification header"))?;
+    let sample_rate = u32::from_le_bytes(bytes[ident + 12..ident + 16].try_into().unwrap());
+    if sample_rate == 0 {
+        return Err(String::from("sample rate is 0"));
+    }
+
+    let end = reader.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+    reader
+        .seek(SeekFrom::Start(end.saturating_sub(SCAN).max(start)))
+        .map_err(|e| e.to_string())?;
+    bytes.clear();
+    reader.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    reader
+        .seek(SeekFrom::Start(start))
+        .map_err(|e| e.to_string())?;
+    let samples = bytes
+        .windows(4)
+        .rposition(|window| window == b"OggS")
+        .filter(|i| i + 14 <= bytes.len())
+        .map(|i| u64::from_le_bytes(bytes[i + 6..i + 14].try_into().unwrap()))
+        .filter(|&granule| granule != u64::MAX)
+        .unwrap_or(0);
+    Ok(samples as f64 / sample_rate as f64)
+}
+
+#[cfg(feature = "ogg")]
+fn find(bytes: &[u8], needle: &[u8]) -> Option<usize> {
+    bytes
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Streams FLAC data
+#[cfg(feature = "flac")]
+struct FlacDecoder<R: Read + Seek + Send + 'static> {
+    reader: Option<claxon::FlacReader<R>>,
+    buffer: IntoIter<i16>,
+    block: Vec<i32>,
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u32,
+    samples: u64,
+}
+
+#[cfg(feature = "flac")]
+impl<R: Read + Seek + Send + 'static> FlacDecoder<R> {
+    fn new(reader: R) -> Result<Self, claxon::Error> {
+        let reader = claxon::FlacReader::new(reader)?;
+        let info = reader.streaminfo();
+        if info.sample_rate == 0 {
+            return Err(claxon::Error::FormatError("sample rate is 0"));
+        }
+        Ok(Self {
+            channels: info.channels as u16,
+            sample_rate: info.sample_rate,
+            bits_per_sample: info.bits_per_sample,
+            samples: info.samples.unwrap_or(0),
+            reader: Some(reader),
+            buffer: vec![].into_iter(),
+            block: vec![],
+        })
+    }
+
+    fn duration(&self) -> f64 {
+        self.samples as f64 / self.sample_rate as f64
+    }
+
+    /// Decodes the next block into `buffer`, returns false at the end of the data
+    fn next_block(&mut self) -> bool {
+        let Some(reader) = self.reader.as_mut() else {
+            return false;
+        };
+        let block = std::mem::take(&mut self.block);
+        match reader.blocks().read_next_or_eof(block) {
+            Ok(Some(block)) => {
+                let bits = self.bits_per_sample;
+                let mut samples = Vec::with_capacity(block.len() as usize);
+                for i in 0..block.duration() {
+                    for ch in 0..block.channels() {
+                        let sample = block.sample(ch, i);
+                        samples.push(if bits > 16 {
+                            (sample >> (bits - 16)) as i16
+                        } else {
+                            (sample << (16 - bits)) as i16
+                        });
+                    }
+                }
+                self.block = block.into_buffer();
+                self.buffer = samples.into_iter();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(feature = "flac")]
+impl<R: Read + Seek + Send + 'static> SoundSource for FlacDecoder<R> {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn reset(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            let mut source = reader.into_inner();
+            self.reader = source
+                .seek(SeekFrom::Start(0))
+                .ok()
+                .and_then(|_| claxon::FlacReader::new(source).ok());
+        }
+        self.buffer = vec![].into_iter();
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let mut i = 0;
+        while i < buffer.len() {
+            if let Some(sample) = self.buffer.next() {
+                buffer[i] = sample;
+                i += 1;
+            } else if !self.next_block() {
+                break;
+            }
+        }
+        i
+    }
+}
+
+/// Reads the frame count from the Xing/Info or VBRI header if there is one,
+/// otherwise counts the samples in each frame header, leaving `reader` where it started
+///
+/// No audio is decoded
+#[cfg(feature = "mp3")]
+fn mp3_duration<R: Read + Seek>(reader: &mut R) -> Result<f64, String> {
+    let start = reader.stream_position().map_err(|e| e.to_string())?;
+    let result = mp3_samples(reader);
+    reader
+        .seek(SeekFrom::Start(start))
+        .map_err(|e| e.to_string())?;
+    Ok(result
+        .map_err(|e| e.to_string())?
+        .map(|(samples, sample_rate)| samples as f64 / sample_rate as f64)
+        .unwrap_or(0.0))
+}
+
+/// Total samples per channel and the sample rate, or None if there are no frames
+#[cfg(feature = "mp3")]
+fn mp3_samples<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<(u64, u32)>> {
+    let mut id3 = [0; 10];
+    let read = read_up_to(reader, &mut id3)?;
+    if read == 10 && id3.starts_with(b"ID3") {
+        let size = id3[6..10]
+            .iter()
+            .fold(0_i64, |size, byte| (size << 7) | (*byte & 0x7F) as i64);
+        //footer flag
+        let footer = if id3[5] & 0x10 != 0 { 10 } else { 0 };
+        reader.seek(SeekFrom::Current(size + footer))?;
+    } else {
+        reader.seek(SeekFrom::Current(-(read as i64)))?;
+    }
+
+    let mut first = true;
+    let mut sample_rate = 0;
+    let mut samples = 0;
+    let mut header = [0; 4];
+    loop {
+        if read_up_to(reader, &mut header)? < 4 {
+            break;
+        }
+        let Some(frame) = Mp3Frame::parse(header) else {
+            //not a frame, try the next byte
+            reader.seek(SeekFrom::Current(-3))?;
+            continue;
+        };
+        if first {
+            first = false;
+            sample_rate = frame.sample_rate;
+            let mut body = vec![0; frame.len - 4];
+            let read = read_up_to(reader, &mut body)?;
+            if let Some(frames) = frame.vbr_frames(&body[..read]) {
+                return Ok(Some((frames as u64 * frame.samples, sample_rate)));
+            }
+            reader.seek(SeekFrom::Current(-(read as i64)))?;
+        }
+        samples += frame.samples;
+        reader.seek(SeekFrom::Current(frame.len as i64 - 4))?;
+    }
+    Ok((samples > 0).then_some((samples, sample_rate)))
+}
+
+/// Like [Read::read_exact] but returns how much was read at the end of the data
+#[cfg(feature = "mp3")]
+fn read_up_to<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buffer.len() {
+        match reader.read(&mut buffer[total..]) {
+            Ok(0) => break,
+            Ok(count) => total += count,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
+
+/// MPEG audio frame header
+#[cfg(feature = "mp3")]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Mp3Frame {
+    /// Bytes including the header
+    len: usize,
+    /// Samples per channel
+    samples: u64,
+    sample_rate: u32,
+    mpeg1: bool,
+    mono: bool,
+}
+
+#[cfg(feature = "mp3")]
+impl Mp3Frame {
+    /// Returns None if `header` isn't a valid frame header, free format frames aren't supported
+    fn parse(header: [u8; 4]) -> Option<Mp3Frame> {
+        const BITRATES: [[u32; 15]; 5] = [
+            //MPEG 1 layer I, II, III
+            [
+                0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+            ],
+            [
+                0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+            ],
+            [
+                0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+            ],
+            //MPEG 2 and 2.5 layer I, II and III
+            [
+                0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+            ],
+            [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        ];
+        const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+        if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+        let version = (header[1] >> 3) & 3;
+        let layer = (header[1] >> 1) & 3;
+        let bitrate = (header[2] >> 4) as usize;
+        let rate = ((header[2] >> 2) & 3) as usize;
+        if version == 1 || layer == 0 || bitrate == 0 || bitrate == 15 || rate == 3 {
+            return None;
+        }
+        let mpeg1 = version == 3;
+        //layer bits are 3 for layer I and 1 for layer III
+        let layer = 4 - layer as usize;
+        let table = match (mpeg1, layer) {
+            (true, _) => layer - 1,
+            (false, 1) => 3,
+            (false, _) => 4,
+        };
+        let bitrate = BITRATES[table][bitrate] * 1000;
+        let sample_rate = SAMPLE_RATES[rate]
+            >> match version {
+                3 => 0,
+                2 => 1,
+                _ => 2,
+            };
+        let padding = ((header[2] >> 1) & 1) as u32;
+        let samples = match layer {
+            1 => 384,
+            3 if !mpeg1 => 576,
+            _ => 1152,
+        };
+        let len = if layer == 1 {
+            (12 * bitrate / sample_rate + padding) * 4
+        } else {
+            samples / 8 * bitrate / sample_rate + padding
+        };
+        Some(Mp3Frame {
+            len: (len as usize).max(4),
+            samples: samples as u64,
+            sample_rate,
+            mpeg1,
+            mono: header[3] >> 6 == 3,
+        })
+    }
+
+    /// Frame count from a Xing/Info or VBRI header, `body` is the first frame without its header
+    fn vbr_frames(&self, body: &[u8]) -> Option<u32> {
+        let be_u32 = |i: usize| {
+            body.get(i..i + 4)
+                .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+        };
+        //Xing is after the side info
+        let xing = match (self.mpeg1, self.mono) {
+            (true, false) => 32,
+            (true, true) | (false, false) => 17,
+            (false, true) => 9,
+        };
+        if let Some(tag) = body.get(xing..xing + 4) {
+            //the frame count flag
+            if (tag == b"Xing" || tag == b"Info") && be_u32(xing + 4)? & 1 != 0 {
+                return be_u32(xing + 8);
+            }
+        }
+        if body.get(32..36) == Some(b"VBRI") {
+            return be_u32(32 + 14);
+        }
+        None
+    }
+}
+
+/// Streams MP3 data
+#[cfg(feature = "mp3")]
+struct Mp3Decoder<R: Read + Seek + Send + 'static> {
+    decoder: Option<minimp3::Decoder<R>>,
+    buffer: IntoIter<i16>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+#[cfg(feature = "mp3")]
+impl<R: Read + Seek + Send + 'static> Mp3Decoder<R> {
+    fn new(reader: R) -> Result<Self, minimp3::Error> {
+        let mut decoder = minimp3::Decoder::new(reader);
+        let frame = decoder.next_frame()?;
+        Ok(Self {
+            channels: frame.channels as u16,
+            sample_rate: frame.sample_rate as u32,
+            buffer: frame.data.into_iter(),
+            decoder: Some(decoder),
+        })
+    }
+}
+
+#[cfg(feature = "mp3")]
+impl<R: Read + Seek + Send + 'static> SoundSource for Mp3Decoder<R> {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn reset(&mut self) {
+        if let Some(decoder) = self.decoder.take() {
+            let mut source = decoder.into_inner();
+            if source.seek(SeekFrom::Start(0)).is_ok() {
+                self.decoder = Some(minimp3::Decoder::new(source));
+            }
+        }
+        self.buffer = vec![].into_iter();
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let mut i = 0;
+        while i < buffer.len() {
+            if let Some(sample) = self.buffer.next() {
+                buffer[i] = sample;
+                i += 1;
+            } else {
+                match self.decoder.as_mut().map(|decoder| decoder.next_frame()) {
+                    Some(Ok(frame)) => self.buffer = frame.data.into_iter(),
+                    _ => break,
+                }
+            }
+        }
+        i
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn wav(channels: u16, sample_rate: u32, samples: usize) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut bytes = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+        for i in 0..samples {
+            writer.write_sample(i as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(
+            SoundFormat::detect(&wav(1, 8000, 0)),
+            Some(SoundFormat::Wav)
+        );
+        assert_eq!(SoundFormat::detect(b"OggS\0\x02"), Some(SoundFormat::Ogg));
+        assert_eq!(SoundFormat::detect(b"fLaC\0\0\0"), Some(SoundFormat::Flac));
+        assert_eq!(SoundFormat::detect(b"ID3\x04"), Some(SoundFormat::Mp3));
+        assert_eq!(
+            SoundFormat::detect(&[0xFF, 0xFB, 0x90]),
+            Some(SoundFormat::Mp3)
+        );
+        assert_eq!(SoundFormat::detect(b"RIFF\0\0\0\0AVI "), None);
+        assert_eq!(SoundFormat::detect(b""), None);
+        assert!(SoundFormat::Wav.is_supported());
+    }
+
+    #[test]
+    fn duration() {
+        let mut mono = Cursor::new(wav(1, 44100, 22050));
+        assert_eq!(wav_duration(&mut mono).unwrap(), 0.5);
+        assert_eq!(mono.position(), 0);
+        let stereo = SoundDecoder::new(Cursor::new(wav(2, 8000, 32000))).unwrap();
+        assert_eq!(stereo.format(), SoundFormat::Wav);
+        assert_eq!(stereo.duration(), 2.0);
+        assert_eq!(stereo.channels(), 2);
+        assert_eq!(stereo.sample_rate(), 8000);
+        let unprobed = SoundDecoder::without_duration(Cursor::new(wav(2, 8000, 32000))).unwrap();
+        assert_eq!(unprobed.duration(), 0.0);
+        assert_eq!(unprobed.channels(), 2);
+    }
+
+    #[test]
+    fn streaming() {
+        let mut decoder = SoundDecoder::new(Cursor::new(wav(1, 8000, 10))).unwrap();
+        let mut buffer = [0; 8];
+        assert_eq!(decoder.write_samples(&mut buffer), 8);
+        assert_eq!(buffer, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(decoder.write_samples(&mut buffer), 2);
+        decoder.reset();
+        assert_eq!(decoder.write_samples(&mut buffer[..2]), 2);
+        assert_eq!(buffer[..2], [0, 1]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            open_file(Path::new("missing/sound.wav")),
+            Err(GameUtilError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            SoundDecoder::new(Cursor::new(vec![0_u8; 8])),
+            Err(GameUtilError::UnsupportedSound(_))
+        ));
+        assert!(matches!(
+            wav_duration(&mut Cursor::new(b"RIFF\0\0\0\0WAVE".to_vec())),
+            Err(GameUtilError::SoundEffectInvalid(_))
+        ));
+        for (format, bytes) in [
+            (SoundFormat::Ogg, b"OggS\0\x02\0\0\0\0\0\0".to_vec()),
+            (SoundFormat::Flac, b"fLaC\0\0\0\0\0\0\0\0".to_vec()),
+            (SoundFormat::Mp3, b"ID3\x04\0\0\0\0\0\0\0\0".to_vec()),
+        ] {
+            let result = SoundDecoder::new(Cursor::new(bytes));
+            if format.is_supported() {
+                assert!(
+                    matches!(result, Err(GameUtilError::SoundDecode(_))),
+                    "{format}"
+                );
+            } else {
+                assert!(
+                    matches!(result, Err(GameUtilError::UnsupportedSound(_))),
+                    "{format}"
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "mp3")]
+    fn mp3_frames(tag: bool, frames: &[[u8; 4]]) -> Vec<u8> {
+        let mut bytes = vec![];
+        if tag {
+            //ID3v2 with 200 bytes of tags, the size is 7 bits per byte
+            bytes.extend_from_slice(b"ID3\x04\0\0\0\0\x01\x48");
+            bytes.extend_from_slice(&[0; 200]);
+        }
+        for header in frames {
+            let frame = Mp3Frame::parse(*header).unwrap();
+            bytes.extend_from_slice(header);
+            bytes.resize(bytes.len() + frame.len - 4, 0);
+        }
+        bytes
+    }
+
+    #[cfg(feature = "mp3")]
+    #[test]
+    fn mp3_headers() {
+        //MPEG 1 layer III, 128 kbps, 44100 Hz, stereo
+        let cbr = [0xFF, 0xFB, 0x90, 0x00];
+        let frame = Mp3Frame::parse(cbr).unwrap();
+        assert_eq!(frame.len, 417);
+        assert_eq!(frame.samples, 1152);
+        assert_eq!(frame.sample_rate, 44100);
+        //padded
+        assert_eq!(Mp3Frame::parse([0xFF, 0xFB, 0x92, 0x00]).unwrap().len, 418);
+        //MPEG 2 layer III, 64 kbps, 22050 Hz, mono
+        let frame = Mp3Frame::parse([0xFF, 0xF3, 0x80, 0xC0]).unwrap();
+        assert_eq!(frame.samples, 576);
+        assert_eq!(frame.sample_rate, 22050);
+        assert_eq!(frame.len, 208);
+        assert_eq!(Mp3Frame::parse([0xFF, 0xFB, 0xF0, 0x00]), None);
+        assert_eq!(Mp3Frame::parse([0xFF, 0xFB, 0x9C, 0x00]), None);
+
+        let mut bytes = Cursor::new(mp3_frames(true, &[cbr; 10]));
+        assert_eq!(mp3_duration(&mut bytes).unwrap(), 11520.0 / 44100.0);
+        assert_eq!(bytes.position(), 0);
+
+        //junk between frames is skipped
+        let mut junk = mp3_frames(false, &[cbr; 2]);
+        junk.extend_from_slice(b"TAG junk");
+        junk.extend_from_slice(&mp3_frames(false, &[cbr]));
+        assert_eq!(
+            mp3_duration(&mut Cursor::new(junk)).unwrap(),
+            3456.0 / 44100.0
+        );
+
+        for tag in [b"Xing", b"Info", b"VBRI"] {
+            let mut bytes = mp3_frames(false, &[cbr; 2]);
+            //after the header and side info
+            let start = 36;
+            bytes[start..start + 4].copy_from_slice(tag);
+            if tag == b"VBRI" {
+                bytes[start + 14..start + 18].copy_from_slice(&500_u32.to_be_bytes());
+            } else {
+                bytes[start + 4..start + 8].copy_from_slice(&1_u32.to_be_bytes());
+                bytes[start + 8..start + 12].copy_from_slice(&500_u32.to_be_bytes());
+            }
+            assert_eq!(
+                mp3_duration(&mut Cursor::new(bytes)).unwrap(),
+                500.0 * 1152.0 / 44100.0,
+                "{}",
+                String::from_utf8_lossy(tag)
+            );
+        }
+        assert_eq!(mp3_duration(&mut Cursor::new(vec![])).unwrap(), 0.0);
+    }
+}
@@ -0,0 +1,247 @@
+use std::f32::consts::FRAC_PI_2;
+
+/// Curve used for fades, maps progress from 0.0..=1.0 to 0.0..=1.0
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Starts slow
+    QuadIn,
+    /// Ends slow
+    QuadOut,
+    QuadInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => t * (2.0 - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    -1.0 + (4.0 - 2.0 * t) * t
+                }
+            }
+            Easing::SineIn => 1.0 - (t * FRAC_PI_2).cos(),
+            Easing::SineOut => (t * FRAC_PI_2).sin(),
+            Easing::SineInOut => (1.0 - (t * 2.0 * FRAC_PI_2).cos()) / 2.0,
+        }
+    }
+
+    /// Swaps in and out, so fading out with the mirror of a fade in makes the same shape backwards
+    pub fn mirrored(&self) -> Easing {
+        match self {
+            Easing::QuadIn => Easing::QuadOut,
+            Easing::QuadOut => Easing::QuadIn,
+            Easing::SineIn => Easing::SineOut,
+            Easing::SineOut => Easing::SineIn,
+            other => *other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Fade {
+    from: f32,
+    to: f32,
+    duration: f64,
+    elapsed: f64,
+    easing: Easing,
+    //Stop playback at the end
+    stop: bool,
+}
+
+/// Volume with an optional fade, used by [Fadeable] types
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Fader {
+    //Volume set by the user, restored after fading out
+    volume: f32,
+    //Volume currently applied to the sound
+    current: f32,
+    fade: Option<Fade>,
+}
+
+impl Default for Fader {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            current: 1.0,
+            fade: None,
+        }
+    }
+}
+
+impl Fader {
+    /// Cancels any fade
+    pub(crate) fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        self.current = volume;
+        self.fade = None;
+    }
+
+    pub(crate) fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub(crate) fn current(&self) -> f32 {
+        self.current
+    }
+
+    pub(crate) fn is_fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    pub(crate) fn fade_to(&mut self, volume: f32, duration: f64, easing: Easing) {
+        self.volume = volume;
+        self.start(volume, duration, easing, false);
+    }
+
+    /// Fade to the user volume, from silent unless already `playing`
+    ///
+    /// The caller must start playback
+    pub(crate) fn fade_in(&mut self, duration: f64, easing: Easing, playing: bool) {
+        if !playing {
+            self.current = 0.0;
+        }
+        self.start(self.volume, duration, easing, false);
+    }
+
+    /// Fade to silent, [Fader::update_secs] returns true when playback should stop
+    pub(crate) fn fade_out(&mut self, duration: f64, easing: Easing) {
+        self.start(0.0, duration, easing, true);
+    }
+
+    fn start(&mut self, to: f32, duration: f64, easing: Easing, stop: bool) {
+        self.fade = Some(Fade {
+            from: self.current,
+            to,
+            duration: duration.max(0.0),
+            elapsed: 0.0,
+            easing,
+            stop,
+        });
+    }
+
+    /// Advance the fade, the caller should apply [Fader::current] if this returns `Some`
+    ///
+    /// Returns `Some(true)` when a fade out has finished and playback should stop
+    pub(crate) fn update_secs(&mut self, delta: f64) -> Option<bool> {
+        let fade = self.fade.as_mut()?;
+        fade.elapsed += delta;
+        if fade.elapsed >= fade.duration {
+            let stop = fade.stop;
+            self.fade = None;
+            self.current = self.volume;
+            Some(stop)
+        } else {
+            let t = fade.easing.apply((fade.elapsed / fade.duration) as f32);
+            self.current = fade.from + (fade.to - fade.from) * t;
+            Some(false)
+        }
+    }
+}
+
+/// Volume fading, advanced by `update`/`update_secs` of the implementing type
+pub trait Fadeable {
+    /// Volume set by the user, or being faded to
+    fn volume(&self) -> f32;
+
+    /// Change volume over `duration` seconds
+    fn fade_to(&mut self, volume: f32, duration: f64, easing: Easing);
+
+    /// Start playing silently and fade up to [Fadeable::volume] over `duration` seconds
+    fn fade_in(&mut self, duration: f64, easing: Easing);
+
+    /// Fade to silent over `duration` seconds then stop, the volume is restored afterwards
+    fn fade_out(&mut self, duration: f64, easing: Easing);
+
+    fn is_fading(&self) -> bool;
+}
+
+/// Fade `from` out and `to` in over the same `duration`, both keep playing during the fade
+///
+/// `easing` is used for `to` and mirrored for `from`, with [Easing::SineOut] the combined loudness stays constant
+pub fn crossfade<A: Fadeable, B: Fadeable>(
+    from: &mut A,
+    to: &mut B,
+    duration: f64,
+    easing: Easing,
+) {
+    from.fade_out(duration, easing.mirrored());
+    to.fade_in(duration, easing);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn easing() {
+        let all = [
+            Easing::Linear,
+            Easing::QuadIn,
+            Easing::QuadOut,
+            Easing::QuadInOut,
+            Easing::SineIn,
+            Easing::SineOut,
+            Easing::SineInOut,
+        ];
+        for easing in all {
+            assert!(easing.apply(0.0).abs() < 0.0001, "{easing:?}");
+            assert!((easing.apply(1.0) - 1.0).abs() < 0.0001, "{easing:?}");
+            assert!((easing.apply(2.0) - 1.0).abs() < 0.0001, "{easing:?}");
+            assert_eq!(easing.mirrored().mirrored(), easing);
+        }
+        assert!(Easing::QuadIn.apply(0.5) < 0.5);
+        assert!(Easing::QuadOut.apply(0.5) > 0.5);
+        assert_eq!(Easing::QuadInOut.apply(0.5), 0.5);
+        //equal power
+        for t in [0.1, 0.3, 0.5, 0.9] {
+            let fade_in = Easing::SineOut.apply(t);
+            let fade_out = 1.0 - Easing::SineIn.apply(t);
+            assert!((fade_in * fade_in + fade_out * fade_out - 1.0).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn fading() {
+        let mut fader = Fader::default();
+        assert_eq!(fader.update_secs(0.1), None);
+        fader.set_volume(0.8);
+        fader.fade_in(1.0, Easing::Linear, false);
+        assert_eq!(fader.current(), 0.0);
+        assert_eq!(fader.update_secs(0.5), Some(false));
+        assert!((fader.current() - 0.4).abs() < 0.0001);
+        assert_eq!(fader.update_secs(0.5), Some(false));
+        assert_eq!(fader.current(), 0.8);
+        assert!(!fader.is_fading());
+
+        fader.fade_to(0.2, 0.5, Easing::QuadIn);
+        assert_eq!(fader.volume(), 0.2);
+        fader.update_secs(0.25);
+        assert!((fader.current() - 0.65).abs() < 0.0001);
+        fader.update_secs(1.0);
+        assert_eq!(fader.current(), 0.2);
+
+        fader.fade_out(1.0, Easing::Linear);
+        assert_eq!(fader.update_secs(0.5), Some(false));
+        assert!((fader.current() - 0.1).abs() < 0.0001);
+        assert_eq!(fader.update_secs(0.5), Some(true));
+        assert_eq!(fader.current(), 0.2);
+
+        fader.fade_out(1.0, Easing::Linear);
+        fader.update_secs(0.5);
+        fader.fade_in(1.0, Easing::Linear, true);
+        assert_eq!(fader.update_secs(0.5), Some(false));
+        assert!((fader.current() - 0.15).abs() < 0.0001);
+        fader.set_volume(0.5);
+        assert_eq!(fader.update_secs(0.5), None);
+    }
+}
@@ -0,0 +1,550 @@
+use crate::error::GameUtilError;
+use crate::sound::backend::{AudioBackend, SoundHandle};
+use crate::sound::decoder::{open_file, SoundDecoder};
+use crate::sound::spatial::{SoundPos, Spatial};
+use crate::sound::track::{free, TrackSource, TrackState};
+use crate::timing::Timing;
+use audio_engine::AudioEngine;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// Mixing group, each has its own volume and can be muted
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum SoundGroup {
+    Music,
+    #[default]
+    Sfx,
+    Ui,
+    Voice,
+}
+
+impl SoundGroup {
+    pub const ALL: [SoundGroup; 4] = [
+        SoundGroup::Music,
+        SoundGroup::Sfx,
+        SoundGroup::Ui,
+        SoundGroup::Voice,
+    ];
+}
+
+/// What happens when a sound is played while it already has [SoundSettings::max_voices] playing
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum VoicePolicy {
+    /// Stop the voice that started first and play a new one
+    #[default]
+    StealOldest,
+    /// Don't play
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoundSettings {
+    /// Multiplied by the group volume
+    pub volume: f32,
+    /// Max number of times this sound can be playing at once, minimum of 1
+    pub max_voices: usize,
+    pub policy: VoicePolicy,
+    /// Looping voices play until stopped
+    pub loops: bool,
+    /// Stereo position, from -1.0 (left) to 1.0 (right), replaced by [SoundSettings::spatial] for voices with a position
+    pub pan: f32,
+    /// Attenuation and panning for voices started with [SoundManager::play_at]
+    pub spatial: Option<Spatial>,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            max_voices: 1,
+            policy: VoicePolicy::default(),
+            loops: false,
+            pan: 0.0,
+            spatial: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GroupSettings {
+    volume: f32,
+    muted: bool,
+}
+
+impl Default for GroupSettings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            muted: false,
+        }
+    }
+}
+
+/// What to do when a sound is played
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum VoiceAction {
+    Start,
+    StealOldest,
+    Ignore,
+}
+
+impl SoundSettings {
+    /// Gain and pan for a voice at `pos`
+    fn gain_and_pan(&self, listener: SoundPos, pos: Option<SoundPos>) -> (f32, f32) {
+        match (self.spatial, pos) {
+            (Some(spatial), Some(pos)) => spatial.gain_and_pan(listener, pos),
+            _ => (1.0, self.pan),
+        }
+    }
+
+    fn voice_action(&self, playing: usize) -> VoiceAction {
+        if playing < self.max_voices.max(1) {
+            VoiceAction::Start
+        } else {
+            match self.policy {
+                VoicePolicy::StealOldest => VoiceAction::StealOldest,
+                VoicePolicy::Ignore => VoiceAction::Ignore,
+            }
+        }
+    }
+}
+
+struct Voice {
+    sound: SoundHandle,
+    //Looping is done by TrackSource rather than the engine so voices can be freed
+    state: Arc<TrackState>,
+    //Emitter position for spatial sounds
+    pos: Option<SoundPos>,
+}
+
+impl Voice {
+    fn apply(&self, settings: &SoundSettings, listener: SoundPos) {
+        let (gain, pan) = settings.gain_and_pan(listener, self.pos);
+        self.state.set_gain(gain);
+        self.state.set_pan(pan);
+    }
+}
+
+impl Drop for Voice {
+    fn drop(&mut self) {
+        free(&mut self.sound, &self.state);
+    }
+}
+
+struct ManagedSound {
+    //Encoded file, each voice decodes it separately
+    data: Arc<[u8]>,
+    group: SoundGroup,
+    //Length in seconds
+    duration: f64,
+    settings: SoundSettings,
+    //Oldest first
+    voices: Vec<Voice>,
+}
+
+/// Owns sounds by key, with group volumes and multiple voices per sound
+///
+/// Unlike [SoundEffect](crate::sound_effect::SoundEffect) playing a sound that is already playing
+/// starts another voice, up to [SoundSettings::max_voices]
+///
+/// You must call [SoundManager::update] or [SoundManager::update_secs] often so finished voices are freed
+///
+/// # Usage
+///
+/// ```no_run
+///# fn main() {
+///# use audio_engine::AudioEngine;
+///# use simple_game_utils::prelude::*;
+///# let mut timing = Timing::new(240);
+/// let mut sounds = SoundManager::new(AudioEngine::new().unwrap());
+/// sounds.add_from_path("shoot", SoundGroup::Sfx, "sounds/shoot.wav").unwrap();
+/// sounds.set_settings("shoot", SoundSettings { max_voices: 4, ..SoundSettings::default() });
+/// sounds.set_group_volume(SoundGroup::Sfx, 0.5);
+/// loop {
+///     timing.update();
+///     sounds.update(&timing);
+///     sounds.play("shoot").unwrap();
+/// }
+///# }
+/// ```
+pub struct SoundManager<B: AudioBackend = AudioEngine> {
+    engine: B,
+    sounds: HashMap<String, ManagedSound>,
+    groups: HashMap<SoundGroup, GroupSettings>,
+    listener: SoundPos,
+}
+
+impl<B: AudioBackend> Debug for SoundManager<B> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SoundManager: sounds: {}, voices: {}",
+            self.sounds.len(),
+            self.sounds.values().map(|s| s.voices.len()).sum::<usize>()
+        )
+    }
+}
+
+impl<B: AudioBackend> SoundManager<B> {
+    /// `engine` is usually an [AudioEngine], or an [OfflineBackend](crate::sound::backend::OfflineBackend) for tests
+    pub fn new(engine: B) -> Self {
+        Self {
+            engine,
+            sounds: HashMap::new(),
+            groups: HashMap::new(),
+            listener: SoundPos::default(),
+        }
+    }
+
+    /// For loading [MusicTrack](crate::sound::music::MusicTrack)s or other sounds not managed by this
+    #[inline]
+    pub fn engine(&self) -> &B {
+        &self.engine
+    }
+
+    /// For rendering with an [OfflineBackend](crate::sound::backend::OfflineBackend)
+    #[inline]
+    pub fn engine_mut(&mut self) -> &mut B {
+        &mut self.engine
+    }
+}
+
+impl<B: AudioBackend> SoundManager<B> {
+    /// Add or replace the sound for `key`, see [SoundDecoder] for supported formats
+    ///
+    /// Replacing a sound stops all its voices
+    ///
+    /// # Errors
+    ///
+    /// The errors from [SoundDecoder::new]
+    pub fn add(
+        &mut self,
+        key: &str,
+        group: SoundGroup,
+        bytes: Vec<u8>,
+    ) -> Result<(), GameUtilError> {
+        let data: Arc<[u8]> = bytes.into();
+        let duration = SoundDecoder::new(Cursor::new(data.clone()))?.duration();
+        self.remove(key);
+        self.sounds.insert(
+            key.to_string(),
+            ManagedSound {
+                data,
+                group,
+                duration,
+                settings: SoundSettings::default(),
+                voices: vec![],
+            },
+        );
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// [GameUtilError::FileNotFound] if `path` doesn't exist,
+    /// [GameUtilError::ReadingFile] if it can't be read,
+    /// or the errors from [SoundDecoder::new]
+    pub fn add_from_path<P: AsRef<Path>>(
+        &mut self,
+        key: &str,
+        group: SoundGroup,
+        path: P,
+    ) -> Result<(), GameUtilError> {
+        let path = path.as_ref();
+        let mut bytes = vec![];
+        open_file(path)?
+            .read_to_end(&mut bytes)
+            .map_err(|e| GameUtilError::from_io(path, e))?;
+        self.add(key, group, bytes)
+    }
+
+    /// Stops and removes the sound, returns false if `key` doesn't exist
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.sounds.remove(key).is_some()
+    }
+
+    #[inline]
+    pub fn contains(&self, key: &str) -> bool {
+        self.sounds.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.sounds.keys()
+    }
+
+    pub fn settings(&self, key: &str) -> Option<&SoundSettings> {
+        self.sounds.get(key).map(|sound| &sound.settings)
+    }
+
+    /// Volume, loop, pan and spatial changes also apply to voices already playing
+    ///
+    /// Returns false if `key` doesn't exist
+    pub fn set_settings(&mut self, key: &str, settings: SoundSettings) -> bool {
+        let Some(sound) = self.sounds.get_mut(key) else {
+            return false;
+        };
+        sound.settings = settings;
+        let volume = Self::volume(&self.groups, sound);
+        for voice in &mut sound.voices {
+            voice.sound.set_volume(volume);
+            voice.state.loops.store(settings.loops, Ordering::Relaxed);
+            voice.apply(&settings, self.listener);
+        }
+        true
+    }
+
+    pub fn group_of(&self, key: &str) -> Option<SoundGroup> {
+        self.sounds.get(key).map(|sound| sound.group)
+    }
+
+    /// Length in seconds
+    pub fn duration(&self, key: &str) -> Option<f64> {
+        self.sounds.get(key).map(|sound| sound.duration)
+    }
+}
+
+impl<B: AudioBackend> SoundManager<B> {
+    /// Play a new voice of `key`
+    ///
+    /// Returns false if the sound already has [SoundSettings::max_voices] playing and
+    /// the policy is [VoicePolicy::Ignore]
+    ///
+    /// # Errors
+    ///
+    /// * [GameUtilError::UnknownSound] if `key` doesn't exist
+    /// * [GameUtilError::SoundEffectInit] if the engine can't create the sound
+    /// * the errors from [SoundDecoder::new]
+    pub fn play(&mut self, key: &str) -> Result<bool, GameUtilError> {
+        self.start(key, None)
+    }
+
+    /// Play a new voice of `key` at `pos`, attenuated and panned using [SoundSettings::spatial]
+    ///
+    /// See [SoundManager::play] for the return value and errors
+    pub fn play_at<P: Into<SoundPos>>(&mut self, key: &str, pos: P) -> Result<bool, GameUtilError> {
+        self.start(key, Some(pos.into()))
+    }
+
+    fn start(&mut self, key: &str, pos: Option<SoundPos>) -> Result<bool, GameUtilError> {
+        let Some(sound) = self.sounds.get_mut(key) else {
+            return Err(GameUtilError::UnknownSound(key.to_string()));
+        };
+        match sound.settings.voice_action(sound.voices.len()) {
+            VoiceAction::Ignore => return Ok(false),
+            VoiceAction::StealOldest => {
+                sound.voices.remove(0);
+            }
+            VoiceAction::Start => {}
+        }
+        //the duration was found in add()
+        let decoder = SoundDecoder::without_duration(Cursor::new(sound.data.clone()))?;
+        let state = Arc::new(TrackState::default());
+        state.loops.store(sound.settings.loops, Ordering::Relaxed);
+        let mut voice = self
+            .engine
+            .add_sound(Box::new(TrackSource::new(decoder, state.clone())))?;
+        voice.set_volume(Self::volume(&self.groups, sound));
+        let mut voice = Voice {
+            sound: voice,
+            state,
+            pos,
+        };
+        voice.apply(&sound.settings, self.listener);
+        voice.sound.play();
+        sound.voices.push(voice);
+        Ok(true)
+    }
+
+    /// Stop all voices of `key`
+    pub fn stop(&mut self, key: &str) {
+        if let Some(sound) = self.sounds.get_mut(key) {
+            Self::stop_voices(sound);
+        }
+    }
+
+    /// Stop all voices of all sounds in `group`
+    pub fn stop_group(&mut self, group: SoundGroup) {
+        self.sounds
+            .values_mut()
+            .filter(|sound| sound.group == group)
+            .for_each(Self::stop_voices);
+    }
+
+    pub fn stop_all(&mut self) {
+        self.sounds.values_mut().for_each(Self::stop_voices);
+    }
+
+    /// Number of voices of `key` currently playing
+    pub fn voices(&self, key: &str) -> usize {
+        self.sounds
+            .get(key)
+            .map(|sound| sound.voices.len())
+            .unwrap_or_default()
+    }
+
+    /// If any voices of `key` are playing
+    pub fn is_playing(&self, key: &str) -> bool {
+        self.voices(key) > 0
+    }
+
+    /// Frees finished voices
+    pub fn update(&mut self, timing: &Timing) {
+        self.update_secs(timing.fixed_time_step)
+    }
+
+    /// Frees finished voices
+    pub fn update_secs(&mut self, _delta: f64) {
+        for sound in self.sounds.values_mut() {
+            sound
+                .voices
+                .retain(|voice| !voice.state.finished.load(Ordering::Relaxed));
+        }
+    }
+
+    /// Move all voices of `key` started with [SoundManager::play_at], call each frame if the emitter moves
+    pub fn set_sound_pos<P: Into<SoundPos>>(&mut self, key: &str, pos: P) {
+        let pos = pos.into();
+        if let Some(sound) = self.sounds.get_mut(key) {
+            for voice in sound.voices.iter_mut().filter(|v| v.pos.is_some()) {
+                voice.pos = Some(pos);
+                voice.apply(&sound.settings, self.listener);
+            }
+        }
+    }
+
+    /// Where spatial sounds are heard from, usually [Tilemap::listener_pos](crate::tiles::tilemap::Tilemap::listener_pos)
+    #[inline]
+    pub fn listener(&self) -> SoundPos {
+        self.listener
+    }
+
+    /// Move the listener and update all voices, call each frame if it moves
+    pub fn set_listener<P: Into<SoundPos>>(&mut self, pos: P) {
+        self.listener = pos.into();
+        for sound in self.sounds.values() {
+            for voice in &sound.voices {
+                voice.apply(&sound.settings, self.listener);
+            }
+        }
+    }
+
+    fn stop_voices(sound: &mut ManagedSound) {
+        sound.voices.clear();
+    }
+}
+
+impl<B: AudioBackend> SoundManager<B> {
+    pub fn group_volume(&self, group: SoundGroup) -> f32 {
+        self.groups.get(&group).copied().unwrap_or_default().volume
+    }
+
+    pub fn set_group_volume(&mut self, group: SoundGroup, volume: f32) {
+        self.groups.entry(group).or_default().volume = volume;
+        self.apply_group(group);
+    }
+
+    pub fn is_group_muted(&self, group: SoundGroup) -> bool {
+        self.groups.get(&group).copied().unwrap_or_default().muted
+    }
+
+    /// Muted groups keep playing silently
+    pub fn set_group_muted(&mut self, group: SoundGroup, muted: bool) {
+        self.groups.entry(group).or_default().muted = muted;
+        self.apply_group(group);
+    }
+
+    fn apply_group(&mut self, group: SoundGroup) {
+        for sound in self.sounds.values_mut().filter(|s| s.group == group) {
+            let volume = Self::volume(&self.groups, sound);
+            for voice in &mut sound.voices {
+                voice.sound.set_volume(volume);
+            }
+        }
+    }
+
+    fn volume(groups: &HashMap<SoundGroup, GroupSettings>, sound: &ManagedSound) -> f32 {
+        group_volume(groups, sound.group, &sound.settings)
+    }
+}
+
+/// Final volume of a sound in `group`
+fn group_volume(
+    groups: &HashMap<SoundGroup, GroupSettings>,
+    group: SoundGroup,
+    settings: &SoundSettings,
+) -> f32 {
+    let group = groups.get(&group).copied().unwrap_or_default();
+    if group.muted {
+        0.0
+    } else {
+        group.volume * settings.volume
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn voice_actions() {
+        let settings = SoundSettings {
+            max_voices: 2,
+            ..SoundSettings::default()
+        };
+        assert_eq!(settings.voice_action(0), VoiceAction::Start);
+        assert_eq!(settings.voice_action(1), VoiceAction::Start);
+        assert_eq!(settings.voice_action(2), VoiceAction::StealOldest);
+        let settings = SoundSettings {
+            max_voices: 0,
+            policy: VoicePolicy::Ignore,
+            ..SoundSettings::default()
+        };
+        assert_eq!(settings.voice_action(0), VoiceAction::Start);
+        assert_eq!(settings.voice_action(1), VoiceAction::Ignore);
+    }
+
+    #[test]
+    fn volumes() {
+        let mut groups = HashMap::new();
+        let settings = SoundSettings {
+            volume: 0.5,
+            ..SoundSettings::default()
+        };
+        assert_eq!(group_volume(&groups, SoundGroup::Sfx, &settings), 0.5);
+        groups.insert(
+            SoundGroup::Sfx,
+            GroupSettings {
+                volume: 0.5,
+                muted: false,
+            },
+        );
+        assert_eq!(group_volume(&groups, SoundGroup::Sfx, &settings), 0.25);
+        assert_eq!(group_volume(&groups, SoundGroup::Ui, &settings), 0.5);
+        groups.get_mut(&SoundGroup::Sfx).unwrap().muted = true;
+        assert_eq!(group_volume(&groups, SoundGroup::Sfx, &settings), 0.0);
+    }
+
+    #[test]
+    fn spatial() {
+        let listener = SoundPos::new(100.0, 0.0);
+        let mut settings = SoundSettings {
+            pan: -0.5,
+            ..SoundSettings::default()
+        };
+        let right = Some(SoundPos::new(300.0, 0.0));
+        assert_eq!(settings.gain_and_pan(listener, None), (1.0, -0.5));
+        assert_eq!(settings.gain_and_pan(listener, right), (1.0, -0.5));
+        settings.spatial = Some(Spatial {
+            min_distance: 0.0,
+            max_distance: 400.0,
+            pan_distance: 200.0,
+            ..Spatial::default()
+        });
+        assert_eq!(settings.gain_and_pan(listener, None), (1.0, -0.5));
+        assert_eq!(settings.gain_and_pan(listener, right), (0.5, 1.0));
+    }
+}
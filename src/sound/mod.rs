@@ -0,0 +1,24 @@
+pub mod backend;
+pub mod decoder;
+pub mod fade;
+pub mod manager;
+pub mod music;
+pub(crate) mod pcm;
+pub(crate) mod rng;
+pub mod spatial;
+pub mod synth;
+pub(crate) mod track;
+pub mod tracker;
+pub mod variant;
+
+pub mod prelude {
+    pub use crate::sound::backend::*;
+    pub use crate::sound::decoder::*;
+    pub use crate::sound::fade::*;
+    pub use crate::sound::manager::*;
+    pub use crate::sound::music::*;
+    pub use crate::sound::spatial::*;
+    pub use crate::sound::synth::*;
+    pub use crate::sound::tracker::*;
+    pub use crate::sound::variant::*;
+}
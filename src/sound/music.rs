@@ -0,0 +1,365 @@
+use crate::error::GameUtilError;
+use crate::sound::backend::{AudioBackend, SoundHandle};
+use crate::sound::decoder::{open_file, SoundDecoder};
+use crate::sound::fade::{Easing, Fadeable, Fader};
+use crate::sound::spatial::{SoundPos, Spatial};
+use crate::sound::track::{free, TrackSource, TrackState};
+use crate::sound::tracker::{Song, SONG_SAMPLE_RATE};
+use crate::timing::Timing;
+use audio_engine::SoundSource;
+use std::fmt::{Debug, Formatter};
+use std::io::{Cursor, Read, Seek};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// Music streamed from a file or reader as it plays
+///
+/// Unlike [SoundEffect](crate::sound_effect::SoundEffect) it can loop from a point after the start, so
+/// an intro plays once and then the rest loops seamlessly
+///
+/// You must call [MusicTrack::update] or [MusicTrack::update_secs] often to keep the position and state current
+///
+/// # Usage
+///
+/// ```no_run
+///# fn main() {
+///# use audio_engine::AudioEngine;
+///# use simple_game_utils::prelude::*;
+///# let mut timing = Timing::new(240);
+/// let mut engine = AudioEngine::new().unwrap();
+/// let mut music = engine.load_music_from_path("music/level1.ogg").unwrap();
+/// music.set_loop(true);
+/// music.set_loop_start(12.5);
+/// music.play();
+/// loop {
+///     timing.update();
+///     music.update(&timing);
+///     println!("{:.1}s", music.position());
+/// }
+///# }
+/// ```
+pub struct MusicTrack {
+    sound: SoundHandle,
+    state: Arc<TrackState>,
+    sample_rate: u32,
+    //Length in seconds
+    duration: f64,
+    is_playing: bool,
+    //Last position read from `state`, in frames
+    last_frames: u64,
+    //Position in seconds, advanced between audio thread updates
+    position: f64,
+    fader: Fader,
+}
+
+impl Debug for MusicTrack {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Music: is_playing: {}, position: {:.1}s, duration: {:.1}s, loops: {}, loop_start: {:.1}s",
+            self.is_playing,
+            self.position,
+            self.duration,
+            self.loops(),
+            self.loop_start()
+        )
+    }
+}
+
+/// Load WAV, Ogg Vorbis, FLAC, MP3 or a [Song] as a [MusicTrack]
+///
+/// # Errors
+///
+/// All methods return [GameUtilError::SoundEffectInit] if the engine can't create the sound,
+/// and the methods loading encoded data return the errors from [SoundDecoder::new]
+pub trait NewMusicTrack {
+    fn load_music_from_vec(&self, bytes: Vec<u8>) -> Result<MusicTrack, GameUtilError>;
+
+    /// # Errors
+    ///
+    /// [GameUtilError::FileNotFound] if `path` doesn't exist,
+    /// or [GameUtilError::ReadingFile] if it can't be opened
+    fn load_music_from_path<P: AsRef<Path>>(&self, path: P) -> Result<MusicTrack, GameUtilError>;
+
+    fn load_music_from_reader<R: Read + Seek + Send + 'static>(
+        &self,
+        reader: R,
+    ) -> Result<MusicTrack, GameUtilError>;
+
+    /// Play a [Song] generated as it plays, looping and the loop start are set from the song
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidSong] if the song can't be parsed
+    fn load_music_from_song(&self, song: &Song) -> Result<MusicTrack, GameUtilError>;
+}
+
+impl<B: AudioBackend + ?Sized> NewMusicTrack for B {
+    fn load_music_from_vec(&self, bytes: Vec<u8>) -> Result<MusicTrack, GameUtilError> {
+        self.load_music_from_reader(Cursor::new(bytes))
+    }
+
+    fn load_music_from_path<P: AsRef<Path>>(&self, path: P) -> Result<MusicTrack, GameUtilError> {
+        self.load_music_from_reader(open_file(path.as_ref())?)
+    }
+
+    fn load_music_from_reader<R: Read + Seek + Send + 'static>(
+        &self,
+        reader: R,
+    ) -> Result<MusicTrack, GameUtilError> {
+        let decoder = SoundDecoder::new(reader)?;
+        let duration = decoder.duration();
+        new_music(self, decoder, duration)
+    }
+
+    fn load_music_from_song(&self, song: &Song) -> Result<MusicTrack, GameUtilError> {
+        let source = song.source(SONG_SAMPLE_RATE)?;
+        let duration = source.duration();
+        let loop_start = source.loop_start();
+        let mut music = new_music(self, source, duration)?;
+        music.set_loop(song.loops);
+        music.set_loop_start(loop_start);
+        Ok(music)
+    }
+}
+
+fn new_music<B: AudioBackend + ?Sized, S: SoundSource + Send + 'static>(
+    backend: &B,
+    source: S,
+    duration: f64,
+) -> Result<MusicTrack, GameUtilError> {
+    let sample_rate = source.sample_rate().max(1);
+    let state = Arc::new(TrackState::default());
+    let sound = backend.add_sound(Box::new(TrackSource::new(source, state.clone())))?;
+    Ok(MusicTrack {
+        sound,
+        state,
+        sample_rate,
+        duration,
+        is_playing: false,
+        last_frames: 0,
+        position: 0.0,
+        fader: Fader::default(),
+    })
+}
+
+impl Drop for MusicTrack {
+    fn drop(&mut self) {
+        free(&mut self.sound, &self.state);
+    }
+}
+
+impl MusicTrack {
+    /// Start or resume playback
+    pub fn play(&mut self) {
+        if !self.is_playing {
+            self.state.finished.store(false, Ordering::Relaxed);
+            self.sound.play();
+            self.is_playing = true;
+        }
+    }
+
+    /// Pause playback, [MusicTrack::play] will continue from the same position
+    pub fn pause(&mut self) {
+        self.sound.pause();
+        self.is_playing = false;
+    }
+
+    /// Reset playback position and stop playback
+    pub fn reset(&mut self) {
+        self.sound.stop();
+        self.state.cancel_seek();
+        self.is_playing = false;
+        self.last_frames = 0;
+        self.position = 0.0;
+    }
+
+    /// Cancels any fade
+    pub fn set_volume(&mut self, volume: f32) {
+        self.fader.set_volume(volume);
+        self.sound.set_volume(volume);
+    }
+
+    /// Set the stereo position, from -1.0 (left) to 1.0 (right)
+    pub fn set_pan(&mut self, pan: f32) {
+        self.state.set_pan(pan);
+    }
+
+    pub fn pan(&self) -> f32 {
+        self.state.pan()
+    }
+
+    /// Attenuate and pan the track for an `emitter` heard from `listener`, call each frame if either moves
+    ///
+    /// This is applied on top of the volume
+    pub fn set_spatial(&mut self, spatial: &Spatial, listener: SoundPos, emitter: SoundPos) {
+        let (gain, pan) = spatial.gain_and_pan(listener, emitter);
+        self.state.set_gain(gain);
+        self.state.set_pan(pan);
+    }
+
+    /// Move playback to `seconds`, clamped to the duration
+    ///
+    /// This decodes from the start of the file up to `seconds`
+    pub fn seek(&mut self, seconds: f64) {
+        let seconds = seconds.clamp(0.0, self.duration);
+        let frame = (seconds * self.sample_rate as f64).round() as u64;
+        self.state.request_seek(frame);
+        self.last_frames = frame;
+        self.position = seconds;
+    }
+
+    /// Set if the track loops back to [MusicTrack::loop_start] when it ends
+    pub fn set_loop(&mut self, loops: bool) {
+        self.state.loops.store(loops, Ordering::Relaxed);
+    }
+
+    /// Set where the track restarts from when looping, in seconds
+    ///
+    /// Anything before this is only played the first time
+    ///
+    /// Looping decodes from the start of the file up to this point, so very long intros may cause a stutter
+    pub fn set_loop_start(&mut self, seconds: f64) {
+        let seconds = seconds.clamp(0.0, self.duration);
+        let frames = (seconds * self.sample_rate as f64).round() as u64;
+        self.state.loop_start.store(frames, Ordering::Relaxed);
+    }
+
+    /// Keeps the position and playing state current and advances fades
+    pub fn update(&mut self, timing: &Timing) {
+        self.update_secs(timing.fixed_time_step)
+    }
+
+    /// Keeps the position and playing state current and advances fades
+    ///
+    /// The audio thread reports the position in chunks, `delta` is used to advance it in between
+    pub fn update_secs(&mut self, delta: f64) {
+        if let Some(stop) = self.fader.update_secs(delta) {
+            if stop {
+                self.reset();
+            }
+            self.sound.set_volume(self.fader.current());
+        }
+        if self.is_playing && self.state.finished.load(Ordering::Relaxed) {
+            self.is_playing = false;
+        }
+        let frames = self.state.position.load(Ordering::Relaxed);
+        if self.state.pending_seek().is_some() {
+            //position was set by seek
+        } else if frames != self.last_frames {
+            self.last_frames = frames;
+            self.position = frames as f64 / self.sample_rate as f64;
+        } else if self.is_playing {
+            self.position = (self.position + delta).min(self.duration);
+        }
+    }
+
+    /// If the track is currently playing
+    #[inline]
+    pub fn is_playing(&self) -> bool {
+        self.is_playing
+    }
+
+    /// Playback position in seconds
+    #[inline]
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    /// Length in seconds
+    #[inline]
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    /// If the track will loop back to [MusicTrack::loop_start] when it ends
+    pub fn loops(&self) -> bool {
+        self.state.loops.load(Ordering::Relaxed)
+    }
+
+    /// Where the track restarts from when looping, in seconds
+    pub fn loop_start(&self) -> f64 {
+        self.state.loop_start.load(Ordering::Relaxed) as f64 / self.sample_rate as f64
+    }
+}
+
+impl Fadeable for MusicTrack {
+    fn volume(&self) -> f32 {
+        self.fader.volume()
+    }
+
+    fn fade_to(&mut self, volume: f32, duration: f64, easing: Easing) {
+        self.fader.fade_to(volume, duration, easing);
+    }
+
+    fn fade_in(&mut self, duration: f64, easing: Easing) {
+        self.fader.fade_in(duration, easing, self.is_playing);
+        self.sound.set_volume(self.fader.current());
+        self.play();
+    }
+
+    fn fade_out(&mut self, duration: f64, easing: Easing) {
+        self.fader.fade_out(duration, easing);
+    }
+
+    fn is_fading(&self) -> bool {
+        self.fader.is_fading()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sound::backend::OfflineBackend;
+
+    //mono at 10Hz, each sample is its frame number * 100
+    fn ramp(frames: i16) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 10,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut bytes = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+        for i in 0..frames {
+            writer.write_sample(i * 100).unwrap();
+        }
+        writer.finalize().unwrap();
+        bytes.into_inner()
+    }
+
+    fn left(samples: &[i16]) -> Vec<i16> {
+        samples.iter().step_by(2).copied().collect()
+    }
+
+    #[test]
+    fn loop_start_and_position() {
+        let mut backend = OfflineBackend::new(2, 10);
+        let mut music = backend.load_music_from_vec(ramp(20)).unwrap();
+        assert_eq!(music.duration(), 2.0);
+        music.set_loop(true);
+        music.set_loop_start(1.5);
+        assert_eq!(music.loop_start(), 1.5);
+        music.play();
+        let output = left(backend.render_frames(22));
+        let expected: Vec<i16> = (0..20).chain(15..17).map(|i| i * 100).collect();
+        assert_eq!(output, expected);
+        music.update_secs(0.0);
+        assert!(music.is_playing());
+        assert_eq!(music.position(), 1.7);
+
+        music.set_loop(false);
+        let output = left(backend.render_frames(10));
+        assert_eq!(&output[..3], &[1700, 1800, 1900]);
+        assert_eq!(&output[3..], &[0; 7]);
+        music.update_secs(0.0);
+        assert!(!music.is_playing());
+
+        music.reset();
+        music.seek(0.5);
+        music.play();
+        assert_eq!(left(backend.render_frames(2)), &[500, 600]);
+    }
+}
@@ -0,0 +1,69 @@
+use audio_engine::SoundSource;
+use std::sync::Arc;
+
+/// Plays interleaved samples from memory
+#[derive(Debug, Clone)]
+pub(crate) struct PcmSource {
+    samples: Arc<[i16]>,
+    channels: u16,
+    sample_rate: u32,
+    //Next sample
+    pos: usize,
+}
+
+impl PcmSource {
+    pub(crate) fn new(samples: Arc<[i16]>, channels: u16, sample_rate: u32) -> Self {
+        Self {
+            samples,
+            channels,
+            sample_rate,
+            pos: 0,
+        }
+    }
+
+    /// Length in seconds
+    pub(crate) fn duration(&self) -> f64 {
+        let frames = self.samples.len() / self.channels.max(1) as usize;
+        frames as f64 / self.sample_rate.max(1) as f64
+    }
+}
+
+impl SoundSource for PcmSource {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn reset(&mut self) {
+        self.pos = 0;
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let len = buffer.len().min(self.samples.len() - self.pos);
+        buffer[..len].copy_from_slice(&self.samples[self.pos..self.pos + len]);
+        self.pos += len;
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn playback() {
+        let mut source = PcmSource::new(vec![1, 2, 3, 4, 5, 6].into(), 2, 3);
+        assert_eq!(source.duration(), 1.0);
+        let mut buffer = [0; 4];
+        assert_eq!(source.write_samples(&mut buffer), 4);
+        assert_eq!(source.write_samples(&mut buffer), 2);
+        assert_eq!(&buffer[..2], &[5, 6]);
+        assert_eq!(source.write_samples(&mut buffer), 0);
+        source.reset();
+        assert_eq!(source.write_samples(&mut buffer), 4);
+        assert_eq!(buffer, [1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::tiles::tilemap::Tilemap;
+use crate::tiles::units::MapPosition;
+use std::fmt::Debug;
+
+/// Position of a sound emitter or listener, usually in px
+///
+/// Any units can be used as long as the emitter, listener and [Spatial] distances match
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SoundPos {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl SoundPos {
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    /// Centre of `tile` in px, for orthogonal maps that aren't scrolled
+    ///
+    /// Use [Tilemap::sound_pos] for maps that scroll or have a different projection
+    pub fn tile<P: Into<MapPosition>>(tile: P, tile_size: (u32, u32)) -> Self {
+        let tile = tile.into();
+        Self::new(
+            (tile.x as f32 + 0.5) * tile_size.0 as f32,
+            (tile.y as f32 + 0.5) * tile_size.1 as f32,
+        )
+    }
+
+    pub fn distance(&self, other: SoundPos) -> f32 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+}
+
+impl From<(f32, f32)> for SoundPos {
+    fn from((x, y): (f32, f32)) -> Self {
+        Self::new(x, y)
+    }
+}
+
+impl From<(isize, isize)> for SoundPos {
+    fn from((x, y): (isize, isize)) -> Self {
+        Self::new(x as f32, y as f32)
+    }
+}
+
+/// How volume decreases between [Spatial::min_distance] and [Spatial::max_distance]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum Falloff {
+    /// Full volume until max distance
+    None,
+    #[default]
+    Linear,
+    /// Drops quickly near the listener then slowly, like real sound
+    Inverse,
+    /// Drops slowly near the listener then quickly
+    Quadratic,
+}
+
+/// Volume and pan of sounds based on their distance from the listener
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spatial {
+    /// Sounds closer than this are full volume
+    pub min_distance: f32,
+    /// Sounds further than this are silent
+    pub max_distance: f32,
+    pub falloff: Falloff,
+    /// Horizontal distance at which sounds are fully to one side
+    pub pan_distance: f32,
+}
+
+impl Default for Spatial {
+    fn default() -> Self {
+        Self {
+            min_distance: 32.0,
+            max_distance: 512.0,
+            falloff: Falloff::default(),
+            pan_distance: 256.0,
+        }
+    }
+}
+
+impl Spatial {
+    /// Volume (0.0..=1.0) and pan (-1.0..=1.0) for a sound at `emitter` heard from `listener`
+    pub fn gain_and_pan(&self, listener: SoundPos, emitter: SoundPos) -> (f32, f32) {
+        let distance = listener.distance(emitter);
+        let gain = if distance <= self.min_distance {
+            1.0
+        } else if distance >= self.max_distance {
+            0.0
+        } else {
+            let range = self.max_distance - self.min_distance;
+            let t = (distance - self.min_distance) / range;
+            match self.falloff {
+                Falloff::None => 1.0,
+                Falloff::Linear => 1.0 - t,
+                Falloff::Inverse => {
+                    //rescaled so it reaches 0 at max distance
+                    let inverse = self.min_distance.max(1.0) / distance;
+                    let at_max = self.min_distance.max(1.0) / self.max_distance;
+                    ((inverse - at_max) / (1.0 - at_max)).clamp(0.0, 1.0)
+                }
+                Falloff::Quadratic => 1.0 - t * t,
+            }
+        };
+        let pan = if self.pan_distance > 0.0 {
+            ((emitter.x - listener.x) / self.pan_distance).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        (gain, pan)
+    }
+}
+
+impl<Image: Debug + Clone> Tilemap<Image> {
+    /// Centre of the area the map is drawn in, use as the listener for sounds from [Tilemap::sound_pos]
+    pub fn listener_pos(&self) -> SoundPos {
+        let (w, h) = self.render_size();
+        SoundPos::new(w as f32 / 2.0, h as f32 / 2.0)
+    }
+
+    /// Centre of `tile` on screen, works with any projection and scrolling
+    pub fn sound_pos<P: Into<MapPosition>>(&self, tile: P) -> SoundPos {
+        let (x, y) = self.px_for_tile(tile);
+        let (w, h) = self.tile_size();
+        SoundPos::new(x as f32 + w as f32 / 2.0, y as f32 + h as f32 / 2.0)
+    }
+}
+
+/// Left and right channel multipliers for `pan`, centre is full volume in both
+pub(crate) fn pan_gains(pan: f32, gain: f32) -> (f32, f32) {
+    let pan = pan.clamp(-1.0, 1.0);
+    (gain * (1.0 - pan).min(1.0), gain * (1.0 + pan).min(1.0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn falloff() {
+        let listener = SoundPos::new(0.0, 0.0);
+        for falloff in [
+            Falloff::None,
+            Falloff::Linear,
+            Falloff::Inverse,
+            Falloff::Quadratic,
+        ] {
+            let spatial = Spatial {
+                min_distance: 10.0,
+                max_distance: 110.0,
+                falloff,
+                pan_distance: 100.0,
+            };
+            let gain = |x: f32| spatial.gain_and_pan(listener, SoundPos::new(x, 0.0)).0;
+            assert_eq!(gain(5.0), 1.0, "{falloff:?}");
+            assert_eq!(gain(110.0), 0.0, "{falloff:?}");
+            assert!(gain(30.0) >= gain(60.0), "{falloff:?}");
+            assert!(gain(60.0) >= gain(100.0), "{falloff:?}");
+        }
+        let spatial = Spatial {
+            min_distance: 10.0,
+            max_distance: 110.0,
+            ..Spatial::default()
+        };
+        assert_eq!(
+            spatial.gain_and_pan(listener, SoundPos::new(0.0, 60.0)).0,
+            0.5
+        );
+    }
+
+    #[test]
+    fn panning() {
+        let spatial = Spatial::default();
+        let listener = SoundPos::new(100.0, 100.0);
+        let pan = |x: f32| spatial.gain_and_pan(listener, SoundPos::new(x, 100.0)).1;
+        assert_eq!(pan(100.0), 0.0);
+        assert_eq!(pan(228.0), 0.5);
+        assert_eq!(pan(-300.0), -1.0);
+
+        assert_eq!(pan_gains(0.0, 1.0), (1.0, 1.0));
+        assert_eq!(pan_gains(-1.0, 0.5), (0.5, 0.0));
+        assert_eq!(pan_gains(0.5, 1.0), (0.5, 1.0));
+    }
+
+    #[test]
+    fn positions() {
+        assert_eq!(
+            SoundPos::tile(MapPosition::new(1, 2), (8, 8)),
+            SoundPos::new(12.0, 20.0)
+        );
+        assert_eq!(
+            SoundPos::from((3_isize, 4_isize)).distance(SoundPos::default()),
+            5.0
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use crate::sound::rng::Rng;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+
+/// Sample rate used by [NewSoundEffect::load_from_synth](crate::sound_effect::NewSoundEffect::load_from_synth)
+pub const SYNTH_SAMPLE_RATE: u32 = 44100;
+
+/// Noise changes value this many times per wave period
+const NOISE_STEPS: f64 = 32.0;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sawtooth,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+/// Starting points for [SynthParams::preset]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum SynthPreset {
+    Coin,
+    Jump,
+    Laser,
+    Explosion,
+    Hurt,
+    Powerup,
+    Blip,
+}
+
+impl SynthPreset {
+    pub const ALL: [SynthPreset; 7] = [
+        SynthPreset::Coin,
+        SynthPreset::Jump,
+        SynthPreset::Laser,
+        SynthPreset::Explosion,
+        SynthPreset::Hurt,
+        SynthPreset::Powerup,
+        SynthPreset::Blip,
+    ];
+}
+
+/// Parameters for a retro sound effect, in the style of sfxr
+///
+/// Times are in seconds and frequencies in Hz, the same params and seed always produce the same samples
+///
+/// # Usage
+///
+/// ```no_run
+///# fn main() {
+///# use audio_engine::AudioEngine;
+///# use simple_game_utils::prelude::*;
+/// let engine = AudioEngine::new().unwrap();
+/// let mut coin = engine.load_from_synth(&SynthParams::preset(SynthPreset::Coin, 1234)).unwrap();
+/// coin.play();
+///# }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SynthParams {
+    /// Used for noise
+    pub seed: u64,
+    pub waveform: Waveform,
+    /// 0.0..=1.0
+    pub volume: f32,
+    /// Time to reach full volume
+    pub attack: f32,
+    /// Time at full volume
+    pub sustain: f32,
+    /// Extra volume at the start of sustain, fading over the sustain
+    pub punch: f32,
+    /// Time to fade to silent
+    pub decay: f32,
+    /// Starting frequency
+    pub frequency: f32,
+    /// The sound stops if the frequency slides below this, 0.0 to disable
+    pub min_frequency: f32,
+    /// Frequency change in octaves per second
+    pub slide: f32,
+    /// Change of [SynthParams::slide] per second
+    pub delta_slide: f32,
+    /// Vibrato depth in semitones
+    pub vibrato_depth: f32,
+    /// Vibrato speed in Hz
+    pub vibrato_speed: f32,
+    /// Frequency multiplier applied after [SynthParams::arpeggio_time]
+    pub arpeggio: f32,
+    /// Time before the arpeggio, 0.0 to disable
+    pub arpeggio_time: f32,
+    /// Fraction of the wave that is high for [Waveform::Square], 0.05..=0.95
+    pub duty: f32,
+    /// Change of [SynthParams::duty] per second
+    pub duty_sweep: f32,
+}
+
+impl Default for SynthParams {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            waveform: Waveform::default(),
+            volume: 0.5,
+            attack: 0.0,
+            sustain: 0.1,
+            punch: 0.0,
+            decay: 0.2,
+            frequency: 440.0,
+            min_frequency: 0.0,
+            slide: 0.0,
+            delta_slide: 0.0,
+            vibrato_depth: 0.0,
+            vibrato_speed: 0.0,
+            arpeggio: 1.0,
+            arpeggio_time: 0.0,
+            duty: 0.5,
+            duty_sweep: 0.0,
+        }
+    }
+}
+
+impl SynthParams {
+    /// Random variation of `preset`, the same seed always gives the same params
+    pub fn preset(preset: SynthPreset, seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut params = SynthParams {
+            seed,
+            ..SynthParams::default()
+        };
+        match preset {
+            SynthPreset::Coin => {
+                params.waveform = pick(&mut rng, &[Waveform::Square, Waveform::Sawtooth]);
+                params.frequency = rng.range(800.0, 1600.0);
+                params.sustain = rng.range(0.02, 0.1);
+                params.punch = rng.range(0.3, 0.6);
+                params.decay = rng.range(0.1, 0.3);
+                if rng.next_f32() < 0.5 {
+                    params.arpeggio = rng.range(1.3, 1.6);
+                    params.arpeggio_time = rng.range(0.04, 0.1);
+                }
+            }
+            SynthPreset::Jump => {
+                params.duty = rng.range(0.2, 0.5);
+                params.frequency = rng.range(250.0, 500.0);
+                params.slide = rng.range(1.5, 3.0);
+                params.sustain = rng.range(0.05, 0.15);
+                params.decay = rng.range(0.1, 0.2);
+            }
+            SynthPreset::Laser => {
+                params.waveform = pick(
+                    &mut rng,
+                    &[Waveform::Square, Waveform::Sawtooth, Waveform::Sine],
+                );
+                params.frequency = rng.range(500.0, 1500.0);
+                params.min_frequency = rng.range(40.0, 120.0);
+                params.slide = -rng.range(2.0, 6.0);
+                params.duty = rng.range(0.2, 0.5);
+                params.duty_sweep = rng.range(0.0, 1.0);
+                params.sustain = rng.range(0.05, 0.15);
+                params.decay = rng.range(0.05, 0.2);
+            }
+            SynthPreset::Explosion => {
+                params.waveform = Waveform::Noise;
+                params.frequency = rng.range(60.0, 300.0);
+                params.slide = -rng.range(0.5, 1.5);
+                params.sustain = rng.range(0.1, 0.3);
+                params.punch = rng.range(0.2, 0.6);
+                params.decay = rng.range(0.3, 0.6);
+                if rng.next_f32() < 0.5 {
+                    params.vibrato_depth = rng.range(1.0, 3.0);
+                    params.vibrato_speed = rng.range(5.0, 15.0);
+                }
+            }
+            SynthPreset::Hurt => {
+                params.waveform = pick(
+                    &mut rng,
+                    &[Waveform::Square, Waveform::Sawtooth, Waveform::Noise],
+                );
+                params.frequency = rng.range(200.0, 600.0);
+                params.slide = -rng.range(2.0, 4.0);
+                params.sustain = rng.range(0.02, 0.1);
+                params.decay = rng.range(0.1, 0.2);
+            }
+            SynthPreset::Powerup => {
+                params.waveform = pick(&mut rng, &[Waveform::Square, Waveform::Sawtooth]);
+                params.frequency = rng.range(300.0, 700.0);
+                params.slide = rng.range(1.0, 3.0);
+                if rng.next_f32() < 0.5 {
+                    params.vibrato_depth = rng.range(0.5, 1.0);
+                    params.vibrato_speed = rng.range(8.0, 16.0);
+                }
+                params.sustain = rng.range(0.1, 0.3);
+                params.decay = rng.range(0.1, 0.4);
+            }
+            SynthPreset::Blip => {
+                params.waveform = pick(&mut rng, &[Waveform::Square, Waveform::Sine]);
+                params.frequency = rng.range(400.0, 1200.0);
+                params.sustain = rng.range(0.03, 0.08);
+                params.decay = rng.range(0.01, 0.05);
+            }
+        }
+        params
+    }
+
+    /// Length in seconds, the sound may end earlier if [SynthParams::min_frequency] is set
+    pub fn duration(&self) -> f32 {
+        self.attack.max(0.0) + self.sustain.max(0.0) + self.decay.max(0.0)
+    }
+
+    /// Generate mono samples
+    pub fn render(&self, sample_rate: u32) -> Vec<i16> {
+        let rate = sample_rate.max(1) as f64;
+        let len = (self.duration() as f64 * rate).round() as usize;
+        let mut rng = Rng::new(self.seed);
+        let mut samples = Vec::with_capacity(len);
+        let (attack, sustain, decay) = (
+            self.attack.max(0.0) as f64,
+            self.sustain.max(0.0) as f64,
+            self.decay.max(0.0) as f64,
+        );
+        let mut phase = 0.0;
+        let mut octaves = 0.0;
+        let mut slide = self.slide as f64;
+        let mut noise = rng.range(-1.0, 1.0) as f64;
+        for i in 0..len {
+            let t = i as f64 / rate;
+            let envelope = if t < attack {
+                t / attack
+            } else if t < attack + sustain {
+                1.0 + self.punch as f64 * (1.0 - (t - attack) / sustain)
+            } else {
+                1.0 - (t - attack - sustain) / decay
+            };
+
+            slide += self.delta_slide as f64 / rate;
+            octaves += slide / rate;
+            let mut frequency = self.frequency as f64 * 2_f64.powf(octaves);
+            if self.min_frequency > 0.0 && frequency < self.min_frequency as f64 {
+                break;
+            }
+            if self.arpeggio_time > 0.0 && t >= self.arpeggio_time as f64 {
+                frequency *= self.arpeggio as f64;
+            }
+            if self.vibrato_depth != 0.0 {
+                let vibrato = (TAU * self.vibrato_speed as f64 * t).sin();
+                frequency *= 2_f64.powf(self.vibrato_depth as f64 * vibrato / 12.0);
+            }
+
+            phase = advance_phase(phase, frequency / rate, &mut noise, &mut rng);
+            let duty = (self.duty as f64 + self.duty_sweep as f64 * t).clamp(0.05, 0.95);
+            let value = match self.waveform {
+                Waveform::Square => {
+                    if phase < duty {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                Waveform::Sawtooth => phase * 2.0 - 1.0,
+                Waveform::Sine => (TAU * phase).sin(),
+                Waveform::Triangle => 4.0 * (phase - 0.5).abs() - 1.0,
+                Waveform::Noise => noise,
+            };
+            let sample = (value * envelope * self.volume as f64).clamp(-1.0, 1.0);
+            samples.push((sample * i16::MAX as f64) as i16);
+        }
+        samples
+    }
+}
+
+/// Moves `phase` on by `step` (a fraction of a wave period), wrapping at 1.0,
+/// and picks a new `noise` value [NOISE_STEPS] times per period
+pub(crate) fn advance_phase(phase: f64, step: f64, noise: &mut f64, rng: &mut Rng) -> f64 {
+    let next = (phase + step).fract();
+    if (next * NOISE_STEPS).floor() != (phase * NOISE_STEPS).floor() {
+        *noise = rng.range(-1.0, 1.0) as f64;
+    }
+    next
+}
+
+fn pick(rng: &mut Rng, waveforms: &[Waveform]) -> Waveform {
+    waveforms[rng.below(waveforms.len())]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        for preset in SynthPreset::ALL {
+            let params = SynthParams::preset(preset, 99);
+            assert_eq!(params, SynthParams::preset(preset, 99), "{preset:?}");
+            let samples = params.render(SYNTH_SAMPLE_RATE);
+            assert!(!samples.is_empty(), "{preset:?}");
+            assert_eq!(samples, params.render(SYNTH_SAMPLE_RATE), "{preset:?}");
+            assert!(samples.iter().any(|s| s.abs() > 1000), "{preset:?}");
+        }
+        assert_ne!(
+            SynthParams::preset(SynthPreset::Coin, 1),
+            SynthParams::preset(SynthPreset::Coin, 2)
+        );
+    }
+
+    #[test]
+    fn envelope() {
+        let params = SynthParams {
+            volume: 1.0,
+            attack: 0.5,
+            sustain: 0.5,
+            decay: 1.0,
+            frequency: 1.0,
+            waveform: Waveform::Square,
+            duty: 0.95,
+            ..SynthParams::default()
+        };
+        let samples = params.render(10);
+        assert_eq!(samples.len(), 20);
+        assert_eq!(samples[0], 0);
+        assert_eq!(samples[5], i16::MAX);
+        assert_eq!(samples[15], i16::MAX / 2);
+    }
+
+    #[test]
+    fn min_frequency() {
+        let params = SynthParams {
+            frequency: 100.0,
+            min_frequency: 50.0,
+            slide: -1.0,
+            sustain: 2.0,
+            decay: 0.0,
+            ..SynthParams::default()
+        };
+        let samples = params.render(100);
+        assert!((99..=101).contains(&samples.len()), "{}", samples.len());
+    }
+}
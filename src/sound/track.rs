@@ -0,0 +1,439 @@
+use crate::sound::backend::SoundHandle;
+use crate::sound::spatial::pan_gains;
+use audio_engine::SoundSource;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Samples decoded at a time when skipping to the loop start or seeking
+const SKIP_CHUNK: usize = 4096;
+/// Slowest and fastest playback rates
+pub(crate) const MIN_RATE: f32 = 0.05;
+pub(crate) const MAX_RATE: f32 = 20.0;
+
+/// Shared between a sound and its [TrackSource] on the audio thread
+#[derive(Debug)]
+pub(crate) struct TrackState {
+    //Playback position in frames
+    pub(crate) position: AtomicU64,
+    //Frame to restart from when looping
+    pub(crate) loop_start: AtomicU64,
+    pub(crate) loops: AtomicBool,
+    //Set when the end is reached without looping
+    pub(crate) finished: AtomicBool,
+    //Frame to seek to plus one, 0 if no seek is pending
+    seek: AtomicU64,
+    //f32 bits, -1.0 is fully left and 1.0 fully right
+    pan: AtomicU32,
+    //f32 bits, multiplied with the sound volume
+    gain: AtomicU32,
+    //f32 bits, playback speed where 2.0 is twice as fast and an octave higher
+    rate: AtomicU32,
+}
+
+impl Default for TrackState {
+    fn default() -> Self {
+        Self {
+            position: AtomicU64::default(),
+            loop_start: AtomicU64::default(),
+            loops: AtomicBool::default(),
+            finished: AtomicBool::default(),
+            seek: AtomicU64::default(),
+            pan: AtomicU32::new(0.0_f32.to_bits()),
+            gain: AtomicU32::new(1.0_f32.to_bits()),
+            rate: AtomicU32::new(1.0_f32.to_bits()),
+        }
+    }
+}
+
+impl TrackState {
+    /// Seek to `frame` the next time samples are written
+    pub(crate) fn request_seek(&self, frame: u64) {
+        self.seek.store(frame.saturating_add(1), Ordering::Relaxed);
+    }
+
+    pub(crate) fn pending_seek(&self) -> Option<u64> {
+        self.seek.load(Ordering::Relaxed).checked_sub(1)
+    }
+
+    pub(crate) fn cancel_seek(&self) {
+        self.seek.store(0, Ordering::Relaxed);
+    }
+
+    fn take_seek(&self) -> Option<u64> {
+        self.seek.swap(0, Ordering::Relaxed).checked_sub(1)
+    }
+
+    pub(crate) fn set_pan(&self, pan: f32) {
+        self.pan
+            .store(pan.clamp(-1.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn pan(&self) -> f32 {
+        f32::from_bits(self.pan.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_gain(&self, gain: f32) {
+        self.gain.store(gain.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn gain(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_rate(&self, rate: f32) {
+        self.rate
+            .store(rate.clamp(MIN_RATE, MAX_RATE).to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn rate(&self) -> f32 {
+        f32::from_bits(self.rate.load(Ordering::Relaxed))
+    }
+}
+
+/// Wraps a decoder to handle looping, panning, playback rate and track the position
+///
+/// Mono sources are output as stereo so they can be panned
+pub(crate) struct TrackSource<S: SoundSource> {
+    source: S,
+    state: Arc<TrackState>,
+    channels: usize,
+    //Mono samples before panning
+    buffer: Vec<i16>,
+    //Source samples not yet resampled, from `input_start`
+    input: Vec<i16>,
+    input_start: usize,
+    //Position between the first two frames of `input`
+    frac: f64,
+}
+
+impl<S: SoundSource> TrackSource<S> {
+    pub(crate) fn new(source: S, state: Arc<TrackState>) -> Self {
+        Self {
+            channels: source.channels().max(1) as usize,
+            source,
+            state,
+            buffer: vec![],
+            input: vec![],
+            input_start: 0,
+            frac: 0.0,
+        }
+    }
+
+    /// Restart from `frame`, returns the new position in frames
+    ///
+    /// This decodes everything before `frame` as sources can't seek
+    fn skip_to(&mut self, frame: u64) -> u64 {
+        self.source.reset();
+        let mut remaining = frame as usize * self.channels;
+        let mut buffer = vec![0; SKIP_CHUNK.min(remaining)];
+        while remaining > 0 {
+            let len = buffer.len().min(remaining);
+            let written = self.source.write_samples(&mut buffer[..len]);
+            remaining -= written;
+            if written < len {
+                break;
+            }
+        }
+        frame - (remaining / self.channels) as u64
+    }
+
+    /// Write samples from the source, looping and seeking as needed
+    fn fill(&mut self, buffer: &mut [i16]) -> usize {
+        if self.state.finished.load(Ordering::Relaxed) {
+            return 0;
+        }
+        let mut position = match self.state.take_seek() {
+            Some(frame) => self.skip_to(frame),
+            None => self.state.position.load(Ordering::Relaxed),
+        };
+        let mut len = 0;
+        let mut restarted = false;
+        loop {
+            let written = self.source.write_samples(&mut buffer[len..]);
+            len += written;
+            position += (written / self.channels) as u64;
+            if len == buffer.len() {
+                break;
+            }
+            //stop if the loop is empty, otherwise this would never finish
+            let empty_loop = restarted && written == 0;
+            if !self.state.loops.load(Ordering::Relaxed) || empty_loop {
+                self.state.finished.store(true, Ordering::Relaxed);
+                break;
+            }
+            position = self.skip_to(self.state.loop_start.load(Ordering::Relaxed));
+            restarted = true;
+        }
+        self.state.position.store(position, Ordering::Relaxed);
+        len
+    }
+}
+
+impl<S: SoundSource> TrackSource<S> {
+    /// Write samples at the playback rate
+    fn read(&mut self, buffer: &mut [i16]) -> usize {
+        if self.state.pending_seek().is_some() {
+            self.clear_input();
+        }
+        let rate = self.state.rate() as f64;
+        if rate == 1.0 {
+            //play any samples left from resampling first
+            self.frac = 0.0;
+            let left = &self.input[self.input_start..];
+            let len = left.len().min(buffer.len());
+            buffer[..len].copy_from_slice(&left[..len]);
+            self.input_start += len;
+            if self.input_start == self.input.len() {
+                self.clear_input();
+            }
+            return len + self.fill(&mut buffer[len..]);
+        }
+        let channels = self.channels;
+        let mut written = 0;
+        while written + channels <= buffer.len() {
+            let mut available = self.input.len() - self.input_start;
+            if available < channels * 2 && self.refill(buffer.len()) {
+                available = self.input.len() - self.input_start;
+            }
+            if available < channels {
+                break;
+            }
+            //the last frame is held when the source has ended
+            let next = if available < channels * 2 {
+                0
+            } else {
+                channels
+            };
+            let frame = &self.input[self.input_start..];
+            for c in 0..channels {
+                let a = frame[c] as f64;
+                let b = frame[c + next] as f64;
+                buffer[written + c] = (a + (b - a) * self.frac).round() as i16;
+            }
+            written += channels;
+            self.frac += rate;
+            let skip = self.frac.floor();
+            self.frac -= skip;
+            self.input_start = (self.input_start + skip as usize * channels).min(self.input.len());
+        }
+        written
+    }
+
+    /// Append about `len` samples to `input`, returns false if the source has ended
+    fn refill(&mut self, len: usize) -> bool {
+        let mut input = std::mem::take(&mut self.input);
+        input.drain(..self.input_start);
+        self.input_start = 0;
+        let start = input.len();
+        let len = (len / self.channels).max(2) * self.channels;
+        input.resize(start + len, 0);
+        let written = self.fill(&mut input[start..]);
+        input.truncate(start + written);
+        self.input = input;
+        written > 0
+    }
+
+    fn clear_input(&mut self) {
+        self.input.clear();
+        self.input_start = 0;
+        self.frac = 0.0;
+    }
+}
+
+fn scale(sample: i16, gain: f32) -> i16 {
+    (sample as f32 * gain).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+impl<S: SoundSource> SoundSource for TrackSource<S> {
+    fn channels(&self) -> u16 {
+        if self.channels == 1 {
+            2
+        } else {
+            self.source.channels()
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    //the engine calls this as soon as the track ends, so `finished` is only cleared by MusicTrack::play
+    fn reset(&mut self) {
+        self.source.reset();
+        self.clear_input();
+        self.state.position.store(0, Ordering::Relaxed);
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let (left, right) = pan_gains(self.state.pan(), self.state.gain());
+        match self.channels {
+            1 => {
+                let mut mono = std::mem::take(&mut self.buffer);
+                mono.resize(buffer.len() / 2, 0);
+                let len = self.read(&mut mono);
+                for (frame, sample) in buffer.chunks_exact_mut(2).zip(&mono[..len]) {
+                    frame[0] = scale(*sample, left);
+                    frame[1] = scale(*sample, right);
+                }
+                self.buffer = mono;
+                len * 2
+            }
+            2 => {
+                let len = self.read(buffer);
+                if left != 1.0 || right != 1.0 {
+                    for frame in buffer[..len].chunks_exact_mut(2) {
+                        frame[0] = scale(frame[0], left);
+                        frame[1] = scale(frame[1], right);
+                    }
+                }
+                len
+            }
+            _ => {
+                let len = self.read(buffer);
+                let gain = self.state.gain();
+                if gain != 1.0 {
+                    for sample in &mut buffer[..len] {
+                        *sample = scale(*sample, gain);
+                    }
+                }
+                len
+            }
+        }
+    }
+}
+
+/// Removes a sound using [TrackSource] from the engine once its handle is dropped
+///
+/// The engine only removes sounds when they end while playing, so this plays it with no samples left
+pub(crate) fn free(sound: &mut SoundHandle, state: &TrackState) {
+    state.finished.store(true, Ordering::Relaxed);
+    sound.play();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Counts up from 0 to `len - 1` in mono
+    struct Counter {
+        i: usize,
+        len: usize,
+    }
+
+    impl SoundSource for Counter {
+        fn channels(&self) -> u16 {
+            1
+        }
+
+        fn sample_rate(&self) -> u32 {
+            10
+        }
+
+        fn reset(&mut self) {
+            self.i = 0;
+        }
+
+        fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+            let len = buffer.len().min(self.len - self.i);
+            for (i, sample) in buffer[..len].iter_mut().enumerate() {
+                *sample = (self.i + i) as i16;
+            }
+            self.i += len;
+            len
+        }
+    }
+
+    fn source(len: usize, loop_start: Option<u64>) -> TrackSource<Counter> {
+        let state = Arc::new(TrackState::default());
+        if let Some(start) = loop_start {
+            state.loops.store(true, Ordering::Relaxed);
+            state.loop_start.store(start, Ordering::Relaxed);
+        }
+        TrackSource::new(Counter { i: 0, len }, state)
+    }
+
+    #[test]
+    fn no_loop() {
+        let mut source = source(6, None);
+        let mut buffer = [0; 4];
+        assert_eq!(source.fill(&mut buffer), 4);
+        assert_eq!(source.state.position.load(Ordering::Relaxed), 4);
+        assert_eq!(source.fill(&mut buffer), 2);
+        assert_eq!(&buffer[..2], &[4, 5]);
+        assert!(source.state.finished.load(Ordering::Relaxed));
+        assert_eq!(source.fill(&mut buffer), 0);
+        source.reset();
+        assert_eq!(source.state.position.load(Ordering::Relaxed), 0);
+        assert!(source.state.finished.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn loop_start() {
+        let mut source = source(5, Some(2));
+        let mut buffer = [0; 8];
+        assert_eq!(source.fill(&mut buffer), 8);
+        assert_eq!(buffer, [0, 1, 2, 3, 4, 2, 3, 4]);
+        assert_eq!(source.state.position.load(Ordering::Relaxed), 5);
+        assert_eq!(source.fill(&mut buffer[..3]), 3);
+        assert_eq!(&buffer[..3], &[2, 3, 4]);
+        assert!(!source.state.finished.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn seek() {
+        let mut source = source(6, None);
+        let mut buffer = [0; 2];
+        source.fill(&mut buffer);
+        source.state.request_seek(3);
+        assert_eq!(source.state.pending_seek(), Some(3));
+        assert_eq!(source.fill(&mut buffer), 2);
+        assert_eq!(buffer, [3, 4]);
+        assert_eq!(source.state.position.load(Ordering::Relaxed), 5);
+        assert_eq!(source.state.pending_seek(), None);
+        source.state.request_seek(10);
+        assert_eq!(source.fill(&mut buffer), 0);
+        assert!(source.state.finished.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn empty_loop() {
+        let mut source = source(3, Some(3));
+        let mut buffer = [0; 8];
+        assert_eq!(source.fill(&mut buffer), 3);
+        assert!(source.state.finished.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn pan() {
+        let mut source = source(4, None);
+        assert_eq!(source.channels(), 2);
+        let mut buffer = [0; 4];
+        assert_eq!(source.write_samples(&mut buffer), 4);
+        assert_eq!(buffer, [0, 0, 1, 1]);
+        source.state.set_pan(0.5);
+        source.state.set_gain(0.5);
+        let mut buffer = [0; 6];
+        assert_eq!(source.write_samples(&mut buffer), 4);
+        assert_eq!(&buffer[..4], &[0, 1, 0, 1]);
+        assert_eq!(source.state.position.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn rate() {
+        let mut source = source(8, None);
+        source.state.set_rate(2.0);
+        let mut buffer = [0; 3];
+        assert_eq!(source.read(&mut buffer), 3);
+        assert_eq!(buffer, [0, 2, 4]);
+        source.state.set_rate(0.5);
+        let mut buffer = [0; 4];
+        assert_eq!(source.read(&mut buffer), 4);
+        assert_eq!(buffer, [6, 7, 7, 7]);
+        assert_eq!(source.read(&mut buffer), 0);
+        source.state.set_rate(1.0);
+        source.reset();
+        source.state.finished.store(false, Ordering::Relaxed);
+        assert_eq!(source.read(&mut buffer), 4);
+        assert_eq!(buffer, [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,587 @@
+use crate::error::GameUtilError;
+use crate::sound::rng::Rng;
+use crate::sound::synth::advance_phase;
+use audio_engine::SoundSource;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Sample rate used by [NewMusicTrack::load_music_from_song](crate::sound::music::NewMusicTrack::load_music_from_song)
+pub const SONG_SAMPLE_RATE: u32 = 44100;
+
+/// Sound played by a [Channel]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instrument {
+    /// `duty` is the fraction of the wave that is high, 0.5 is a pure square
+    Square {
+        duty: f32,
+    },
+    Triangle,
+    Noise,
+    /// Mono samples, played at their original speed for the `root` note (MIDI number, 60 is C4)
+    Sample {
+        samples: Vec<i16>,
+        sample_rate: u32,
+        root: u8,
+    },
+}
+
+impl Default for Instrument {
+    fn default() -> Self {
+        Instrument::Square { duty: 0.5 }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub instrument: Instrument,
+    pub volume: f32,
+    /// Seconds for each note to fade out, 0.0 holds notes until the next note or `off`
+    pub decay: f32,
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Self {
+            instrument: Instrument::default(),
+            volume: 0.5,
+            decay: 0.0,
+        }
+    }
+}
+
+/// Rows of notes, with one string per [Channel]
+///
+/// Each string is a list of rows separated by whitespace:
+/// * A note such as `C4`, `F#3` or `Bb5`, optionally with a volume `C4:0.5`
+/// * `.` or `-` to continue the previous note
+/// * `off` to stop the previous note
+///
+/// Channels with fewer rows than the longest are padded with `.`
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Pattern {
+    pub channels: Vec<String>,
+}
+
+impl Pattern {
+    pub fn new(channels: &[&str]) -> Self {
+        Self {
+            channels: channels.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+/// Music made of patterns of notes played on synthesized channels
+///
+/// Play with [NewMusicTrack::load_music_from_song](crate::sound::music::NewMusicTrack::load_music_from_song)
+///
+/// # Usage
+///
+/// ```no_run
+///# fn main() {
+///# use audio_engine::AudioEngine;
+///# use simple_game_utils::prelude::*;
+/// let engine = AudioEngine::new().unwrap();
+/// let song = Song {
+///     tempo: 140.0,
+///     channels: vec![Channel::default(), Channel { instrument: Instrument::Triangle, ..Channel::default() }],
+///     patterns: vec![Pattern::new(&["C4 E4 G4 E4", "C2 . . off"])],
+///     order: vec![0, 0],
+///     ..Song::default()
+/// };
+/// let mut music = engine.load_music_from_song(&song).unwrap();
+/// music.play();
+///# }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Song {
+    /// Beats per minute
+    pub tempo: f32,
+    pub rows_per_beat: u32,
+    pub channels: Vec<Channel>,
+    pub patterns: Vec<Pattern>,
+    /// Indexes of [Song::patterns] in the order they're played
+    pub order: Vec<usize>,
+    pub loops: bool,
+    /// Index of [Song::order] to restart from when looping, must be less than the length of `order`
+    pub loop_start: usize,
+}
+
+impl Default for Song {
+    fn default() -> Self {
+        Self {
+            tempo: 120.0,
+            rows_per_beat: 4,
+            channels: vec![],
+            patterns: vec![],
+            order: vec![],
+            loops: true,
+            loop_start: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+    Empty,
+    Note { frequency: f64, volume: f32 },
+    Off,
+}
+
+/// Song with patterns parsed and laid out in order
+#[derive(Debug)]
+struct Compiled {
+    channels: Vec<Channel>,
+    //Cell for each channel
+    rows: Vec<Vec<Cell>>,
+    //Seconds
+    row_len: f64,
+    loop_start_row: usize,
+}
+
+impl Song {
+    /// Seconds per row
+    pub fn row_len(&self) -> f64 {
+        60.0 / (self.tempo.max(1.0) as f64 * self.rows_per_beat.max(1) as f64)
+    }
+
+    /// Length in seconds, when not looping
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidSong] if the song can't be parsed
+    pub fn duration(&self) -> Result<f64, GameUtilError> {
+        let compiled = self.compile()?;
+        Ok(compiled.rows.len() as f64 * compiled.row_len)
+    }
+
+    /// Where the song restarts when looping, in seconds
+    pub fn loop_start_secs(&self) -> Result<f64, GameUtilError> {
+        let compiled = self.compile()?;
+        Ok(compiled.loop_start_row as f64 * compiled.row_len)
+    }
+
+    /// Generate mono samples for the song once through
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidSong] if the song can't be parsed
+    pub fn render(&self, sample_rate: u32) -> Result<Vec<i16>, GameUtilError> {
+        let mut source = self.source(sample_rate)?;
+        let mut samples = vec![];
+        let mut buffer = [0; 4096];
+        loop {
+            let len = source.write_samples(&mut buffer);
+            samples.extend_from_slice(&buffer[..len]);
+            if len < buffer.len() {
+                return Ok(samples);
+            }
+        }
+    }
+
+    pub(crate) fn source(&self, sample_rate: u32) -> Result<SongSource, GameUtilError> {
+        Ok(SongSource::new(Arc::new(self.compile()?), sample_rate))
+    }
+
+    fn compile(&self) -> Result<Compiled, GameUtilError> {
+        if self.channels.is_empty() {
+            return Err(GameUtilError::InvalidSong(String::from("no channels")));
+        }
+        for (i, channel) in self.channels.iter().enumerate() {
+            if let Instrument::Sample { sample_rate: 0, .. } = channel.instrument {
+                return Err(GameUtilError::InvalidSong(format!(
+                    "channel {i} has a sample rate of 0"
+                )));
+            }
+        }
+        if self.loop_start > 0 && self.loop_start >= self.order.len() {
+            return Err(GameUtilError::InvalidSong(format!(
+                "loop start is {} but the order only has {} entries",
+                self.loop_start,
+                self.order.len()
+            )));
+        }
+        let mut patterns = vec![];
+        for (i, pattern) in self.patterns.iter().enumerate() {
+            if pattern.channels.len() > self.channels.len() {
+                return Err(GameUtilError::InvalidSong(format!(
+                    "pattern {i} has {} channels but the song only has {}",
+                    pattern.channels.len(),
+                    self.channels.len()
+                )));
+            }
+            let channels = pattern
+                .channels
+                .iter()
+                .map(|channel| {
+                    channel
+                        .split_whitespace()
+                        .map(parse_cell)
+                        .collect::<Result<Vec<Cell>, String>>()
+                })
+                .collect::<Result<Vec<_>, String>>()
+                .map_err(|e| GameUtilError::InvalidSong(format!("pattern {i}: {e}")))?;
+            let len = channels.iter().map(Vec::len).max().unwrap_or_default();
+            let rows: Vec<Vec<Cell>> = (0..len)
+                .map(|row| {
+                    (0..self.channels.len())
+                        .map(|c| {
+                            channels
+                                .get(c)
+                                .and_then(|cells| cells.get(row))
+                                .copied()
+                                .unwrap_or(Cell::Empty)
+                        })
+                        .collect()
+                })
+                .collect();
+            patterns.push(rows);
+        }
+        let mut rows = vec![];
+        let mut loop_start_row = 0;
+        for (i, &idx) in self.order.iter().enumerate() {
+            let Some(pattern) = patterns.get(idx) else {
+                return Err(GameUtilError::InvalidSong(format!(
+                    "order {i} is pattern {idx} but there are only {} patterns",
+                    patterns.len()
+                )));
+            };
+            if i == self.loop_start {
+                loop_start_row = rows.len();
+            }
+            rows.extend(pattern.iter().cloned());
+        }
+        Ok(Compiled {
+            channels: self.channels.clone(),
+            rows,
+            row_len: self.row_len(),
+            loop_start_row,
+        })
+    }
+}
+
+fn parse_cell(token: &str) -> Result<Cell, String> {
+    match token {
+        "." | "-" => return Ok(Cell::Empty),
+        "off" => return Ok(Cell::Off),
+        _ => {}
+    }
+    let (note, volume) = match token.split_once(':') {
+        Some((note, volume)) => (
+            note,
+            volume
+                .parse::<f32>()
+                .map_err(|_| format!("invalid volume in {token}"))?,
+        ),
+        None => (token, 1.0),
+    };
+    let midi = parse_note(note).ok_or_else(|| format!("invalid note {token}"))?;
+    Ok(Cell::Note {
+        frequency: note_frequency(midi),
+        volume,
+    })
+}
+
+/// MIDI number for notes such as `C4` or `F#3`
+fn parse_note(note: &str) -> Option<i32> {
+    let mut chars = note.chars();
+    let semitone = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = if let Some(octave) = rest.strip_prefix('#') {
+        (1, octave)
+    } else if let Some(octave) = rest.strip_prefix('b') {
+        (-1, octave)
+    } else {
+        (0, rest)
+    };
+    let octave: i32 = octave.parse().ok()?;
+    Some((octave + 1) * 12 + semitone + accidental)
+}
+
+fn note_frequency(midi: i32) -> f64 {
+    440.0 * 2_f64.powf((midi - 69) as f64 / 12.0)
+}
+
+#[derive(Debug, Clone)]
+struct ChannelState {
+    frequency: f64,
+    volume: f32,
+    playing: bool,
+    //Frames since the note started
+    age: u64,
+    //Position in the wave, or in the sample for samples
+    phase: f64,
+    noise: f64,
+    rng: Rng,
+}
+
+impl ChannelState {
+    fn new(channel: usize) -> Self {
+        Self {
+            frequency: 0.0,
+            volume: 0.0,
+            playing: false,
+            age: 0,
+            phase: 0.0,
+            noise: 0.0,
+            rng: Rng::new(channel as u64),
+        }
+    }
+}
+
+/// Streams a [Song] once through, looping is done by [TrackSource](crate::sound::track::TrackSource)
+pub(crate) struct SongSource {
+    song: Arc<Compiled>,
+    sample_rate: u32,
+    //Frames per row
+    row_frames: f64,
+    frame: u64,
+    //Next row to start
+    row: usize,
+    states: Vec<ChannelState>,
+}
+
+impl SongSource {
+    fn new(song: Arc<Compiled>, sample_rate: u32) -> Self {
+        let sample_rate = sample_rate.max(1);
+        Self {
+            row_frames: song.row_len * sample_rate as f64,
+            states: (0..song.channels.len()).map(ChannelState::new).collect(),
+            song,
+            sample_rate,
+            frame: 0,
+            row: 0,
+        }
+    }
+
+    pub(crate) fn duration(&self) -> f64 {
+        self.song.rows.len() as f64 * self.song.row_len
+    }
+
+    pub(crate) fn loop_start(&self) -> f64 {
+        self.song.loop_start_row as f64 * self.song.row_len
+    }
+
+    fn start_row(&mut self) {
+        for (state, cell) in self.states.iter_mut().zip(&self.song.rows[self.row]) {
+            match *cell {
+                Cell::Empty => {}
+                Cell::Note { frequency, volume } => {
+                    state.frequency = frequency;
+                    state.volume = volume;
+                    state.playing = true;
+                    state.age = 0;
+                    state.phase = 0.0;
+                }
+                Cell::Off => state.playing = false,
+            }
+        }
+        self.row += 1;
+    }
+
+    fn next_sample(&mut self) -> f64 {
+        let rate = self.sample_rate as f64;
+        let mut mix = 0.0;
+        for (state, channel) in self.states.iter_mut().zip(&self.song.channels) {
+            if !state.playing {
+                continue;
+            }
+            let envelope = if channel.decay > 0.0 {
+                1.0 - state.age as f64 / (channel.decay as f64 * rate)
+            } else {
+                1.0
+            };
+            if envelope <= 0.0 {
+                state.playing = false;
+                continue;
+            }
+            state.age += 1;
+            let value = match &channel.instrument {
+                Instrument::Sample {
+                    samples,
+                    sample_rate,
+                    root,
+                } => {
+                    let Some(sample) = samples.get(state.phase as usize) else {
+                        state.playing = false;
+                        continue;
+                    };
+                    let speed = state.frequency / note_frequency(*root as i32);
+                    state.phase += speed * *sample_rate as f64 / rate;
+                    *sample as f64 / i16::MAX as f64
+                }
+                instrument => {
+                    state.phase = advance_phase(
+                        state.phase,
+                        state.frequency / rate,
+                        &mut state.noise,
+                        &mut state.rng,
+                    );
+                    match instrument {
+                        Instrument::Square { duty } => {
+                            if state.phase < *duty as f64 {
+                                1.0
+                            } else {
+                                -1.0
+                            }
+                        }
+                        Instrument::Triangle => 4.0 * (state.phase - 0.5).abs() - 1.0,
+                        _ => state.noise,
+                    }
+                }
+            };
+            mix += value * envelope * state.volume as f64 * channel.volume as f64;
+        }
+        mix
+    }
+}
+
+impl SoundSource for SongSource {
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn reset(&mut self) {
+        self.frame = 0;
+        self.row = 0;
+        self.states = (0..self.song.channels.len())
+            .map(ChannelState::new)
+            .collect();
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let end = (self.song.rows.len() as f64 * self.row_frames).round() as u64;
+        let mut len = 0;
+        for sample in buffer.iter_mut() {
+            if self.frame >= end {
+                break;
+            }
+            while self.row < self.song.rows.len()
+                && (self.row as f64 * self.row_frames).round() as u64 <= self.frame
+            {
+                self.start_row();
+            }
+            let value = self.next_sample().clamp(-1.0, 1.0);
+            *sample = (value * i16::MAX as f64) as i16;
+            self.frame += 1;
+            len += 1;
+        }
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn notes() {
+        assert_eq!(parse_note("A4"), Some(69));
+        assert_eq!(parse_note("C4"), Some(60));
+        assert_eq!(parse_note("c#4"), Some(61));
+        assert_eq!(parse_note("Bb3"), Some(58));
+        assert_eq!(parse_note("H4"), None);
+        assert_eq!(parse_note("C"), None);
+        assert_eq!(note_frequency(69), 440.0);
+        assert_eq!(parse_cell("."), Ok(Cell::Empty));
+        assert_eq!(parse_cell("off"), Ok(Cell::Off));
+        assert_eq!(
+            parse_cell("A4:0.5"),
+            Ok(Cell::Note {
+                frequency: 440.0,
+                volume: 0.5
+            })
+        );
+        assert!(parse_cell("A4:x").is_err());
+    }
+
+    fn song() -> Song {
+        Song {
+            tempo: 60.0,
+            rows_per_beat: 1,
+            channels: vec![
+                Channel {
+                    volume: 1.0,
+                    ..Channel::default()
+                },
+                Channel {
+                    instrument: Instrument::Sample {
+                        samples: vec![1000; 5],
+                        sample_rate: 10,
+                        root: 69,
+                    },
+                    volume: 1.0,
+                    decay: 0.0,
+                },
+            ],
+            patterns: vec![Pattern::new(&["A4 off", "- A4"]), Pattern::new(&["A4"])],
+            order: vec![1, 0],
+            loops: true,
+            loop_start: 1,
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let song = song();
+        assert_eq!(song.duration().unwrap(), 3.0);
+        assert_eq!(song.loop_start_secs().unwrap(), 1.0);
+        let samples = song.render(10).unwrap();
+        assert_eq!(samples.len(), 30);
+        //square wave restarted on the second row
+        assert!(samples[..20].iter().all(|&s| s == i16::MAX));
+        //square stopped, sample plays for half a second at the root note
+        assert!(samples[20..25].iter().all(|s| (999..=1000).contains(s)));
+        assert!(samples[25..].iter().all(|&s| s == 0));
+        assert_eq!(samples, song.render(10).unwrap());
+    }
+
+    #[test]
+    fn errors() {
+        let mut song = song();
+        song.order.push(2);
+        assert!(matches!(
+            song.duration(),
+            Err(GameUtilError::InvalidSong(_))
+        ));
+        let mut song = self::song();
+        song.patterns[0].channels[0].push_str(" Z9");
+        assert!(song.render(10).is_err());
+        assert!(Song::default().render(10).is_err());
+        let mut song = self::song();
+        song.loop_start = 2;
+        assert!(matches!(
+            song.loop_start_secs(),
+            Err(GameUtilError::InvalidSong(_))
+        ));
+        let mut song = self::song();
+        song.channels[1].instrument = Instrument::Sample {
+            samples: vec![1000; 5],
+            sample_rate: 0,
+            root: 69,
+        };
+        assert!(matches!(
+            song.render(10),
+            Err(GameUtilError::InvalidSong(_))
+        ));
+    }
+}
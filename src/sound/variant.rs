@@ -0,0 +1,188 @@
+use crate::sound::rng::Rng;
+use crate::sound_effect::SoundEffect;
+use crate::timing::Timing;
+
+/// Plays one of several clips at random with a random pitch and volume, so repeated sounds like footsteps vary
+///
+/// You must call [SoundVariant::update] or [SoundVariant::update_secs] often, as with [SoundEffect]
+///
+/// # Usage
+///
+/// ```no_run
+///# fn main() {
+///# use audio_engine::AudioEngine;
+///# use simple_game_utils::prelude::*;
+///# let mut timing = Timing::new(240);
+/// let engine = AudioEngine::new().unwrap();
+/// let clips = ["step1.wav", "step2.wav", "step3.wav"]
+///     .iter()
+///     .map(|path| engine.load_from_path(path).unwrap())
+///     .collect();
+/// let mut steps = SoundVariant::new(clips);
+/// steps.set_pitch_range(-2.0, 2.0);
+/// steps.set_volume_range(0.8, 1.0);
+/// loop {
+///     timing.update();
+///     steps.update(&timing);
+///     steps.play();
+/// }
+///# }
+/// ```
+#[derive(Debug)]
+pub struct SoundVariant {
+    clips: Vec<SoundEffect>,
+    //Semitones
+    pitch: (f32, f32),
+    volume: (f32, f32),
+    avoid_repeats: bool,
+    //Index of the clip played last
+    last: Option<usize>,
+    rng: Rng,
+}
+
+impl SoundVariant {
+    /// Random choices are seeded from the clock, see [SoundVariant::set_seed]
+    pub fn new(clips: Vec<SoundEffect>) -> Self {
+        Self {
+            clips,
+            pitch: (0.0, 0.0),
+            volume: (1.0, 1.0),
+            avoid_repeats: true,
+            last: None,
+            rng: Rng::from_time(),
+        }
+    }
+
+    /// Make the random choices repeatable
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Range of pitch changes in semitones, (0.0, 0.0) by default
+    ///
+    /// This also changes the speed, see [SoundEffect::set_pitch]
+    pub fn set_pitch_range(&mut self, min: f32, max: f32) {
+        self.pitch = (min, max);
+    }
+
+    pub fn pitch_range(&self) -> (f32, f32) {
+        self.pitch
+    }
+
+    /// Range of volumes, (1.0, 1.0) by default
+    pub fn set_volume_range(&mut self, min: f32, max: f32) {
+        self.volume = (min, max);
+    }
+
+    pub fn volume_range(&self) -> (f32, f32) {
+        self.volume
+    }
+
+    /// If the same clip can't be played twice in a row, true by default
+    pub fn set_avoid_repeats(&mut self, avoid_repeats: bool) {
+        self.avoid_repeats = avoid_repeats;
+    }
+
+    pub fn avoid_repeats(&self) -> bool {
+        self.avoid_repeats
+    }
+
+    /// Play a random clip, restarting it if it's already playing
+    ///
+    /// Returns the index of the clip, or `None` if there are no clips
+    pub fn play(&mut self) -> Option<usize> {
+        if self.clips.is_empty() {
+            return None;
+        }
+        let idx = pick(
+            &mut self.rng,
+            self.clips.len(),
+            self.last,
+            self.avoid_repeats,
+        );
+        let semitones = self.rng.range(self.pitch.0, self.pitch.1);
+        let volume = self.rng.range(self.volume.0, self.volume.1);
+        let clip = &mut self.clips[idx];
+        if clip.is_playing() || clip.is_paused() {
+            clip.reset();
+        }
+        clip.set_pitch(semitones);
+        clip.set_volume(volume);
+        clip.play();
+        self.last = Some(idx);
+        Some(idx)
+    }
+
+    /// Stop all clips
+    pub fn reset(&mut self) {
+        self.clips.iter_mut().for_each(SoundEffect::reset);
+    }
+
+    pub fn update(&mut self, timing: &Timing) {
+        self.update_secs(timing.fixed_time_step)
+    }
+
+    pub fn update_secs(&mut self, delta: f64) {
+        for clip in &mut self.clips {
+            clip.update_secs(delta);
+        }
+    }
+
+    /// If any clip is playing
+    pub fn is_playing(&self) -> bool {
+        self.clips.iter().any(SoundEffect::is_playing)
+    }
+
+    /// Index of the clip played last
+    pub fn last(&self) -> Option<usize> {
+        self.last
+    }
+
+    pub fn clips(&self) -> &[SoundEffect] {
+        &self.clips
+    }
+
+    pub fn clips_mut(&mut self) -> &mut [SoundEffect] {
+        &mut self.clips
+    }
+}
+
+/// Random index below `len`, skipping `last` if `avoid_repeats` and there's a choice
+fn pick(rng: &mut Rng, len: usize, last: Option<usize>, avoid_repeats: bool) -> usize {
+    match last {
+        Some(last) if avoid_repeats && len > 1 && last < len => {
+            let idx = rng.below(len - 1);
+            if idx >= last {
+                idx + 1
+            } else {
+                idx
+            }
+        }
+        _ => rng.below(len),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn picking() {
+        let mut rng = Rng::new(7);
+        let mut last = None;
+        let mut counts = [0; 3];
+        for _ in 0..300 {
+            let idx = pick(&mut rng, 3, last, true);
+            assert_ne!(Some(idx), last);
+            counts[idx] += 1;
+            last = Some(idx);
+        }
+        assert!(counts.iter().all(|&c| c > 50));
+        assert_eq!(pick(&mut rng, 1, Some(0), true), 0);
+        let mut a = Rng::new(3);
+        let mut b = Rng::new(3);
+        for _ in 0..10 {
+            assert_eq!(pick(&mut a, 5, None, false), pick(&mut b, 5, None, false));
+        }
+    }
+}
@@ -1,8 +1,18 @@
 use crate::error::GameUtilError;
+use crate::sound::backend::{AudioBackend, SoundHandle};
+use crate::sound::decoder::{open_file, SoundDecoder};
+use crate::sound::fade::{Easing, Fadeable, Fader};
+use crate::sound::pcm::PcmSource;
+use crate::sound::spatial::{SoundPos, Spatial};
+use crate::sound::synth::{SynthParams, SYNTH_SAMPLE_RATE};
+use crate::sound::track::{free, TrackSource, TrackState};
 use crate::timing::Timing;
-use audio_engine::{AudioEngine, Sound, WavDecoder};
+use audio_engine::SoundSource;
 use std::fmt::{Debug, Formatter};
-use std::io::Cursor;
+use std::io::{Cursor, Read, Seek};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
 /// Sound effect (although it can also be used for music)
 /// You must call [SoundEffect::update] or [SoundEffect::update_secs] with accurate values and often otherwise playback may stutter or jump
@@ -29,103 +39,357 @@ use std::io::Cursor;
 /// ```
 pub struct SoundEffect {
     //Sound data
-    sound: Sound,
+    sound: SoundHandle,
     //If sound is currently playing
     is_playing: bool,
+    //If sound was paused while playing
+    paused: bool,
     //Length in seconds
     duration: f64,
     // used to prevent bugs
     next_play_in: f64,
     //If sound automatically loops
     loops: bool,
+    fader: Fader,
+    //Position, seeking and looping, with the sample rate, only for sounds loaded by NewSoundEffect
+    track: Option<(Arc<TrackState>, u32)>,
 }
 
 impl Debug for SoundEffect {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Sound: is_playing: {}, duration: {:.1}s, loops: {}",
-            self.is_playing, self.duration, self.loops
+            "Sound: is_playing: {}, paused: {}, position: {:.1}s, duration: {:.1}s, loops: {}",
+            self.is_playing,
+            self.paused,
+            self.position(),
+            self.duration,
+            self.loops
         )
     }
 }
 
+/// Load WAV, Ogg Vorbis, FLAC or MP3 data as a [SoundEffect]
+///
+/// The format is detected from the data, see [SoundDecoder]
+///
+/// Except for [NewSoundEffect::load_from_bytes] the duration is read from the file,
+/// use [SoundEffect::set_duration] to override it
+///
+/// # Errors
+///
+/// All methods return [GameUtilError::SoundEffectInit] if the engine can't create the sound,
+/// and the methods loading encoded data return the errors from [SoundDecoder::new]
 pub trait NewSoundEffect {
+    /// Load from static bytes, `duration` (in seconds) is used instead of the length in the file
     fn load_from_bytes(
         &self,
         bytes: &'static [u8],
         duration: f64,
     ) -> Result<SoundEffect, GameUtilError>;
+
+    /// Load from bytes read at runtime
+    fn load_from_vec(&self, bytes: Vec<u8>) -> Result<SoundEffect, GameUtilError>;
+
+    /// Load from a file, it's streamed as it plays
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::FileNotFound] if `path` doesn't exist,
+    /// or [GameUtilError::ReadingFile] if it can't be opened
+    fn load_from_path<P: AsRef<Path>>(&self, path: P) -> Result<SoundEffect, GameUtilError>;
+
+    /// Load from any reader, such as a file inside an archive
+    fn load_from_reader<R: Read + Seek + Send + 'static>(
+        &self,
+        reader: R,
+    ) -> Result<SoundEffect, GameUtilError>;
+
+    /// Load interleaved 16 bit samples, such as generated audio
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::SoundEffectInit] if `channels` or `sample_rate` are 0
+    fn load_from_pcm(
+        &self,
+        samples: Vec<i16>,
+        channels: u16,
+        sample_rate: u32,
+    ) -> Result<SoundEffect, GameUtilError>;
+
+    /// Render `params` at 44.1kHz, see [SynthParams::render]
+    fn load_from_synth(&self, params: &SynthParams) -> Result<SoundEffect, GameUtilError>;
 }
 
-impl NewSoundEffect for AudioEngine {
+impl<B: AudioBackend + ?Sized> NewSoundEffect for B {
     fn load_from_bytes(
         &self,
         bytes: &'static [u8],
         duration: f64,
     ) -> Result<SoundEffect, GameUtilError> {
-        let decoder =
-            WavDecoder::new(Cursor::new(bytes)).map_err(GameUtilError::SoundEffectInvalid)?;
-        let sound = self
-            .new_sound(decoder)
-            .map_err(GameUtilError::SoundEffectInit)?;
-        Ok(SoundEffect::new(sound, duration))
+        let mut sound = self.load_from_reader(Cursor::new(bytes))?;
+        sound.set_duration(duration);
+        Ok(sound)
+    }
+
+    fn load_from_vec(&self, bytes: Vec<u8>) -> Result<SoundEffect, GameUtilError> {
+        self.load_from_reader(Cursor::new(bytes))
+    }
+
+    fn load_from_path<P: AsRef<Path>>(&self, path: P) -> Result<SoundEffect, GameUtilError> {
+        self.load_from_reader(open_file(path.as_ref())?)
+    }
+
+    fn load_from_reader<R: Read + Seek + Send + 'static>(
+        &self,
+        reader: R,
+    ) -> Result<SoundEffect, GameUtilError> {
+        let decoder = SoundDecoder::new(reader)?;
+        let duration = decoder.duration();
+        new_tracked(self, decoder, duration)
+    }
+
+    fn load_from_pcm(
+        &self,
+        samples: Vec<i16>,
+        channels: u16,
+        sample_rate: u32,
+    ) -> Result<SoundEffect, GameUtilError> {
+        if channels == 0 || sample_rate == 0 {
+            return Err(GameUtilError::SoundEffectInit(
+                "channels and sample rate must be more than 0",
+            ));
+        }
+        let source = PcmSource::new(samples.into(), channels, sample_rate);
+        let duration = source.duration();
+        new_tracked(self, source, duration)
+    }
+
+    fn load_from_synth(&self, params: &SynthParams) -> Result<SoundEffect, GameUtilError> {
+        self.load_from_pcm(params.render(SYNTH_SAMPLE_RATE), 1, SYNTH_SAMPLE_RATE)
+    }
+}
+
+fn new_tracked<B: AudioBackend + ?Sized, S: SoundSource + Send + 'static>(
+    backend: &B,
+    source: S,
+    duration: f64,
+) -> Result<SoundEffect, GameUtilError> {
+    let sample_rate = source.sample_rate().max(1);
+    let state = Arc::new(TrackState::default());
+    let sound = backend.add_sound(Box::new(TrackSource::new(source, state.clone())))?;
+    let mut effect = SoundEffect::new(sound, duration);
+    effect.track = Some((state, sample_rate));
+    Ok(effect)
+}
+
+impl Drop for SoundEffect {
+    fn drop(&mut self) {
+        if let Some((state, _)) = &self.track {
+            free(&mut self.sound, state);
+        }
     }
 }
 
 impl SoundEffect {
-    pub fn new(sound: Sound, duration: f64) -> Self {
+    /// Prefer [NewSoundEffect], sounds created with this can't [SoundEffect::seek] and
+    /// [SoundEffect::position] is estimated from [SoundEffect::update] calls
+    pub fn new<S: Into<SoundHandle>>(sound: S, duration: f64) -> Self {
         Self {
-            sound,
+            sound: sound.into(),
             is_playing: false,
+            paused: false,
             duration,
             next_play_in: 0.0,
             loops: false,
+            fader: Fader::default(),
+            track: None,
         }
     }
 
-    /// Play sound effect, won't do anything if sound effect is already playing
+    /// Play sound effect from [SoundEffect::position], won't do anything if sound effect is already playing
+    ///
+    /// If paused this is the same as [SoundEffect::resume]
     pub fn play(&mut self) {
-        if !self.is_playing {
+        if self.paused {
+            self.resume();
+        } else if !self.is_playing {
+            if let Some((state, _)) = &self.track {
+                state.finished.store(false, Ordering::Relaxed);
+            }
+            self.next_play_in = self.duration - self.position();
             self.sound.play();
             self.is_playing = true;
-            self.next_play_in = self.duration;
+        }
+    }
+
+    /// Pause playback, won't do anything if the sound isn't playing
+    pub fn pause(&mut self) {
+        if self.is_playing {
+            self.sound.pause();
+            self.is_playing = false;
+            self.paused = true;
+        }
+    }
+
+    /// Continue playback from where it was paused, won't do anything if the sound isn't paused
+    pub fn resume(&mut self) {
+        if self.paused {
+            self.sound.play();
+            self.is_playing = true;
+            self.paused = false;
         }
     }
 
     /// Reset playback position and stop playback
     pub fn reset(&mut self) {
         self.sound.stop();
+        if let Some((state, _)) = &self.track {
+            state.cancel_seek();
+        }
         self.is_playing = false;
+        self.paused = false;
         self.next_play_in = 0.0;
     }
 
+    /// Move playback to `seconds`, clamped to the duration
+    ///
+    /// Works while playing, paused or stopped, when stopped the next [SoundEffect::play] starts from here
+    ///
+    /// Sounds created with [SoundEffect::new] always seek to the start
+    pub fn seek(&mut self, seconds: f64) {
+        let seconds = match &self.track {
+            Some((state, sample_rate)) => {
+                let seconds = seconds.clamp(0.0, self.duration);
+                state.request_seek((seconds * *sample_rate as f64).round() as u64);
+                seconds
+            }
+            None => {
+                self.sound.reset();
+                0.0
+            }
+        };
+        if self.is_playing || self.paused {
+            self.next_play_in = self.duration - seconds;
+        }
+    }
+
+    /// Playback position in seconds
+    ///
+    /// Stopped sounds are at the start, unless [SoundEffect::seek] was called
+    pub fn position(&self) -> f64 {
+        match &self.track {
+            Some((state, sample_rate)) => {
+                let frame = state
+                    .pending_seek()
+                    .unwrap_or_else(|| state.position.load(Ordering::Relaxed));
+                frame as f64 / *sample_rate as f64
+            }
+            None if self.is_playing || self.paused => {
+                (self.duration - self.next_play_in).clamp(0.0, self.duration)
+            }
+            None => 0.0,
+        }
+    }
+
+    /// If paused, see [SoundEffect::pause]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
     /// Set if the sound loops automatically
     pub fn set_loop(&mut self, loops: bool) {
         self.loops = loops;
-        self.sound.set_loop(loops)
+        match &self.track {
+            Some((state, _)) => state.loops.store(loops, Ordering::Relaxed),
+            None => self.sound.set_loop(loops),
+        }
     }
 
+    /// Cancels any fade
     pub fn set_volume(&mut self, volume: f32) {
+        self.fader.set_volume(volume);
         self.sound.set_volume(volume);
     }
 
+    /// Set the stereo position, from -1.0 (left) to 1.0 (right)
+    ///
+    /// Has no effect on sounds made with [SoundEffect::new]
+    pub fn set_pan(&mut self, pan: f32) {
+        if let Some((state, _)) = &self.track {
+            state.set_pan(pan);
+        }
+    }
+
+    pub fn pan(&self) -> f32 {
+        self.track
+            .as_ref()
+            .map(|(state, _)| state.pan())
+            .unwrap_or_default()
+    }
+
+    /// Set the playback speed, 2.0 is twice as fast and an octave higher, clamped to 0.05..=20.0
+    ///
+    /// Has no effect on sounds made with [SoundEffect::new]
+    pub fn set_rate(&mut self, rate: f32) {
+        if let Some((state, _)) = &self.track {
+            state.set_rate(rate);
+        }
+    }
+
+    /// Playback speed, see [SoundEffect::set_rate]
+    pub fn rate(&self) -> f32 {
+        self.track
+            .as_ref()
+            .map(|(state, _)| state.rate())
+            .unwrap_or(1.0)
+    }
+
+    /// Set the playback speed in semitones, 12.0 is an octave higher and twice as fast
+    pub fn set_pitch(&mut self, semitones: f32) {
+        self.set_rate(2_f32.powf(semitones / 12.0));
+    }
+
+    /// Attenuate and pan the sound for an `emitter` heard from `listener`, call each frame if either moves
+    ///
+    /// This is applied on top of the volume and has no effect on sounds made with [SoundEffect::new]
+    pub fn set_spatial(&mut self, spatial: &Spatial, listener: SoundPos, emitter: SoundPos) {
+        if let Some((state, _)) = &self.track {
+            let (gain, pan) = spatial.gain_and_pan(listener, emitter);
+            state.set_gain(gain);
+            state.set_pan(pan);
+        }
+    }
+
     /// Returns true if calling [SoundEffect::play] will do anything
+    ///
+    /// * Playing: false
+    /// * Paused: true, it will resume
+    /// * Stopped: true, except between [SoundEffect::reset] (or the sound ending) and the next update
     pub fn can_play(&self) -> bool {
-        !self.is_playing && self.next_play_in < 0.0
+        self.paused || (!self.is_playing && self.next_play_in < 0.0)
     }
 
-    /// Allows the sound to continue playing
+    /// Allows the sound to continue playing and advances fades
     pub fn update(&mut self, timing: &Timing) {
         self.update_secs(timing.fixed_time_step)
     }
 
-    /// Allows the sound to continue playing
+    /// Allows the sound to continue playing and advances fades
     pub fn update_secs(&mut self, delta: f64) {
+        if let Some(stop) = self.fader.update_secs(delta) {
+            if stop {
+                self.reset();
+            }
+            self.sound.set_volume(self.fader.current());
+        }
         if !self.loops && self.is_playing && self.next_play_in < 0.0 {
             self.reset();
         }
-        self.next_play_in -= delta;
+        if !self.paused {
+            //next_play_in is in seconds of the sound rather than real time
+            self.next_play_in -= delta * self.rate() as f64;
+        }
     }
 
     /// If the sound is currently playing
@@ -133,13 +397,42 @@ impl SoundEffect {
         self.is_playing
     }
 
-    /// Length in seconds
+    /// Length in seconds when played at a rate of 1.0
     pub fn duration(&self) -> f64 {
         self.duration
     }
 
+    /// Override the length in seconds, normally this is read from the file
+    pub fn set_duration(&mut self, duration: f64) {
+        self.duration = duration;
+    }
+
     /// If sound will automatically loop
     pub fn loops(&self) -> bool {
         self.loops
     }
 }
+
+impl Fadeable for SoundEffect {
+    fn volume(&self) -> f32 {
+        self.fader.volume()
+    }
+
+    fn fade_to(&mut self, volume: f32, duration: f64, easing: Easing) {
+        self.fader.fade_to(volume, duration, easing);
+    }
+
+    fn fade_in(&mut self, duration: f64, easing: Easing) {
+        self.fader.fade_in(duration, easing, self.is_playing);
+        self.sound.set_volume(self.fader.current());
+        self.play();
+    }
+
+    fn fade_out(&mut self, duration: f64, easing: Easing) {
+        self.fader.fade_out(duration, easing);
+    }
+
+    fn is_fading(&self) -> bool {
+        self.fader.is_fading()
+    }
+}
@@ -0,0 +1,351 @@
+use std::fmt::Debug;
+use std::rc::Rc;
+
+#[cfg(feature = "ici")]
+use ici_files::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+/// Images that can have a rectangle copied out of them
+pub trait SubImage: Sized {
+    /// Size of the image in px
+    fn image_size(&self) -> (u32, u32);
+
+    /// Copies the area at `x`,`y` (top left) of `w`x`h` px into a new image
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidAtlas] if the area is outside the image
+    fn sub_image(&self, x: u32, y: u32, w: u32, h: u32) -> Result<Self, GameUtilError>;
+}
+
+/// A named area of an atlas image
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AtlasRect {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl AtlasRect {
+    pub fn new(name: &str, x: u32, y: u32, w: u32, h: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            x,
+            y,
+            w,
+            h,
+        }
+    }
+}
+
+/// How tiles are arranged in an atlas image
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AtlasLayout {
+    /// Tiles of the same size in rows and columns
+    Grid {
+        /// Size of each tile in px
+        tile_size: (u32, u32),
+        /// Px around the edge of the image before the first tile
+        #[serde(default)]
+        margin: u32,
+        /// Px between each tile
+        #[serde(default)]
+        spacing: u32,
+        /// Names for the tiles, left to right then top to bottom
+        ///
+        /// If empty every cell is used and named by its index ("0", "1", etc)
+        #[serde(default)]
+        names: Vec<String>,
+    },
+    /// Tiles at specific areas, all rects must be the same size
+    Rects(Vec<AtlasRect>),
+}
+
+impl AtlasLayout {
+    pub fn grid(tile_size: (u32, u32)) -> Self {
+        AtlasLayout::Grid {
+            tile_size,
+            margin: 0,
+            spacing: 0,
+            names: vec![],
+        }
+    }
+
+    /// Returns the tile rects for an image of `image_size` px
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidAtlas] if the layout doesn't fit the image or has no tiles, or
+    /// the rects aren't the same size
+    pub fn rects(&self, image_size: (u32, u32)) -> Result<Vec<AtlasRect>, GameUtilError> {
+        match self {
+            AtlasLayout::Grid {
+                tile_size,
+                margin,
+                spacing,
+                names,
+            } => {
+                if tile_size.0 == 0 || tile_size.1 == 0 {
+                    return Err(GameUtilError::InvalidAtlas(String::from(
+                        "tile size must not be zero",
+                    )));
+                }
+                let overflow =
+                    || GameUtilError::InvalidAtlas(String::from("margin or spacing is too large"));
+                let cells = |len: u32, tile: u32| {
+                    let step = tile.checked_add(*spacing)?;
+                    let inner = len.saturating_sub(margin.checked_mul(2)?);
+                    Some(inner.checked_add(*spacing)? / step)
+                };
+                let columns = cells(image_size.0, tile_size.0).ok_or_else(overflow)?;
+                let rows = cells(image_size.1, tile_size.1).ok_or_else(overflow)?;
+                let count = columns.checked_mul(rows).ok_or_else(overflow)? as usize;
+                if count == 0 {
+                    return Err(GameUtilError::InvalidAtlas(format!(
+                        "no {}x{} tiles fit in {}x{} image",
+                        tile_size.0, tile_size.1, image_size.0, image_size.1
+                    )));
+                }
+                if names.len() > count {
+                    return Err(GameUtilError::InvalidAtlas(format!(
+                        "{} names but image only has {count} tiles",
+                        names.len()
+                    )));
+                }
+                let count = if names.is_empty() { count } else { names.len() };
+                let offset = |cell: u32, tile: u32| {
+                    tile.checked_add(*spacing)
+                        .and_then(|step| step.checked_mul(cell))
+                        .and_then(|px| px.checked_add(*margin))
+                        .ok_or_else(overflow)
+                };
+                (0..count)
+                    .map(|i| {
+                        let column = i as u32 % columns;
+                        let row = i as u32 / columns;
+                        Ok(AtlasRect {
+                            name: names.get(i).cloned().unwrap_or_else(|| i.to_string()),
+                            x: offset(column, tile_size.0)?,
+                            y: offset(row, tile_size.1)?,
+                            w: tile_size.0,
+                            h: tile_size.1,
+                        })
+                    })
+                    .collect()
+            }
+            AtlasLayout::Rects(rects) => {
+                let Some(first) = rects.first() else {
+                    return Err(GameUtilError::InvalidAtlas(String::from("no rects")));
+                };
+                if first.w == 0 || first.h == 0 {
+                    return Err(GameUtilError::InvalidAtlas(String::from(
+                        "tile size must not be zero",
+                    )));
+                }
+                if let Some(rect) = rects.iter().find(|r| r.w != first.w || r.h != first.h) {
+                    return Err(GameUtilError::InvalidAtlas(format!(
+                        "{} is {}x{} but {} is {}x{}",
+                        rect.name, rect.w, rect.h, first.name, first.w, first.h
+                    )));
+                }
+                if let Some(rect) = rects
+                    .iter()
+                    .find(|r| !fits(r.x, r.w, image_size.0) || !fits(r.y, r.h, image_size.1))
+                {
+                    return Err(GameUtilError::InvalidAtlas(format!(
+                        "{} is outside of image",
+                        rect.name
+                    )));
+                }
+                Ok(rects.clone())
+            }
+        }
+    }
+}
+
+/// Returns true if `start` + `len` is within `limit`
+fn fits(start: u32, len: u32, limit: u32) -> bool {
+    start.checked_add(len).is_some_and(|end| end <= limit)
+}
+
+impl<Image: SubImage + Debug + Clone> Tileset<Image> {
+    /// Slice `atlas` into tiles using `layout`
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidAtlas] if the layout doesn't fit the image or has no tiles, or
+    /// the rects aren't the same size
+    pub fn from_atlas(atlas: &Image, layout: &AtlasLayout) -> Result<Self, GameUtilError> {
+        let rects = layout.rects(atlas.image_size())?;
+        let size = match (layout, rects.first()) {
+            (AtlasLayout::Grid { tile_size, .. }, _) => *tile_size,
+            (_, Some(rect)) => (rect.w, rect.h),
+            (_, None) => (0, 0),
+        };
+        let mut images = Vec::with_capacity(rects.len());
+        let mut names = Vec::with_capacity(rects.len());
+        for rect in rects {
+            images.push(Rc::new(atlas.sub_image(rect.x, rect.y, rect.w, rect.h)?));
+            names.push(rect.name);
+        }
+        Ok(Tileset::new(images, names, size))
+    }
+}
+
+#[cfg(feature = "ici")]
+impl SubImage for IndexedImage {
+    fn image_size(&self) -> (u32, u32) {
+        (self.width() as u32, self.height() as u32)
+    }
+
+    fn sub_image(&self, x: u32, y: u32, w: u32, h: u32) -> Result<Self, GameUtilError> {
+        let (width, height) = self.image_size();
+        if !fits(x, w, width) || !fits(y, h, height) {
+            return Err(GameUtilError::InvalidAtlas(format!(
+                "{x},{y} {w}x{h} is outside of {width}x{height} image"
+            )));
+        }
+        let src = self.get_pixels();
+        let mut pixels = Vec::with_capacity((w * h) as usize);
+        for row in y..y + h {
+            let start = (row * width + x) as usize;
+            pixels.extend_from_slice(&src[start..start + w as usize]);
+        }
+        IndexedImage::new(w as u8, h as u8, self.get_palette().to_vec(), pixels)
+            .map_err(|e| GameUtilError::InvalidImage(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grid_rects() {
+        let layout = AtlasLayout::Grid {
+            tile_size: (4, 4),
+            margin: 1,
+            spacing: 2,
+            names: vec![],
+        };
+        let rects = layout.rects((16, 12)).unwrap();
+        assert_eq!(
+            rects,
+            vec![
+                AtlasRect::new("0", 1, 1, 4, 4),
+                AtlasRect::new("1", 7, 1, 4, 4),
+                AtlasRect::new("2", 1, 7, 4, 4),
+                AtlasRect::new("3", 7, 7, 4, 4),
+            ]
+        );
+
+        let layout = AtlasLayout::Grid {
+            tile_size: (4, 4),
+            margin: 0,
+            spacing: 0,
+            names: vec![String::from("a"), String::from("b")],
+        };
+        assert_eq!(
+            layout.rects((8, 8)).unwrap(),
+            vec![
+                AtlasRect::new("a", 0, 0, 4, 4),
+                AtlasRect::new("b", 4, 0, 4, 4)
+            ]
+        );
+        assert!(layout.rects((4, 4)).is_err());
+    }
+
+    #[test]
+    fn grid_overflow_and_empty() {
+        for (margin, spacing) in [(u32::MAX / 2 + 1, 0), (0, u32::MAX), (u32::MAX, u32::MAX)] {
+            let layout = AtlasLayout::Grid {
+                tile_size: (4, 4),
+                margin,
+                spacing,
+                names: vec![],
+            };
+            assert!(
+                matches!(layout.rects((16, 16)), Err(GameUtilError::InvalidAtlas(_))),
+                "{margin} {spacing}"
+            );
+        }
+        let layout = AtlasLayout::grid((u32::MAX, 1));
+        assert!(matches!(
+            layout.rects((u32::MAX, 1)),
+            Ok(rects) if rects == vec![AtlasRect::new("0", 0, 0, u32::MAX, 1)]
+        ));
+        let layout = AtlasLayout::grid((8, 8));
+        assert!(matches!(
+            layout.rects((4, 16)),
+            Err(GameUtilError::InvalidAtlas(_))
+        ));
+    }
+
+    #[test]
+    fn rects_validation() {
+        let layout = AtlasLayout::Rects(vec![
+            AtlasRect::new("a", 0, 0, 2, 2),
+            AtlasRect::new("b", 2, 0, 2, 3),
+        ]);
+        assert!(layout.rects((8, 8)).is_err());
+        let layout = AtlasLayout::Rects(vec![AtlasRect::new("a", 7, 0, 2, 2)]);
+        assert!(layout.rects((8, 8)).is_err());
+        let layout = AtlasLayout::Rects(vec![AtlasRect::new("a", u32::MAX, 0, 2, 2)]);
+        assert!(matches!(
+            layout.rects((8, 8)),
+            Err(GameUtilError::InvalidAtlas(_))
+        ));
+        for layout in [
+            AtlasLayout::Rects(vec![]),
+            AtlasLayout::Rects(vec![AtlasRect::new("a", 0, 0, 0, 0)]),
+        ] {
+            assert!(matches!(
+                layout.rects((8, 8)),
+                Err(GameUtilError::InvalidAtlas(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "ici")]
+    #[test]
+    fn slice_indexed_image() {
+        let palette = vec![Color::new(0, 0, 0, 255), Color::new(255, 255, 255, 255)];
+        #[rustfmt::skip]
+        let atlas = IndexedImage::new(4, 2, palette.clone(), vec![
+            0, 1, 1, 1,
+            1, 0, 1, 1,
+        ]).unwrap();
+        let tileset = Tileset::from_atlas(
+            &atlas,
+            &AtlasLayout::Grid {
+                tile_size: (2, 2),
+                margin: 0,
+                spacing: 0,
+                names: vec![String::from("diag"), String::from("white")],
+            },
+        )
+        .unwrap();
+        assert_eq!(tileset.tilesize(), (2, 2));
+        assert_eq!(
+            tileset.find_by_name("diag").unwrap().get_pixels(),
+            &[0, 1, 1, 0]
+        );
+        assert_eq!(
+            tileset.find_by_name("white").unwrap().get_pixels(),
+            &[1, 1, 1, 1]
+        );
+        assert_eq!(
+            tileset.find_by_name("white").unwrap().get_palette(),
+            palette
+        );
+        assert!(atlas.sub_image(3, 0, 2, 2).is_err());
+        assert!(matches!(
+            atlas.sub_image(0, u32::MAX, 2, 2),
+            Err(GameUtilError::InvalidAtlas(_))
+        ));
+    }
+}
@@ -0,0 +1,650 @@
+//! Compact binary encoding for [TilemapFile] and [IciTileset](crate::tiles::ici::IciTileset)
+//!
+//! Files start with a 4 byte magic ([TILEMAP_MAGIC] or [TILESET_MAGIC]), a version byte and a
+//! compression byte, followed by the (possibly compressed) body.
+//! Numbers are little endian, strings and lists are prefixed with their length as a u32,
+//! maps are written sorted by key and map rows and tile pixels are run length encoded.
+//!
+//! Files that decompress to more than [MAX_DECODED_LEN] bytes or run length decode to more than
+//! [MAX_DECODED_LEN] values in total are rejected.
+
+use std::collections::HashMap;
+#[cfg(feature = "compression")]
+use std::io::{Read, Write};
+
+use crate::prelude::*;
+
+pub const TILEMAP_MAGIC: [u8; 4] = *b"SGUM";
+pub const TILESET_MAGIC: [u8; 4] = *b"SGUT";
+/// Current binary format version, files with a newer version can't be read
+pub const BINARY_VERSION: u8 = 1;
+
+/// Limit for decompressed bodies and run length encoded data, see [crate::tiles::binary]
+pub const MAX_DECODED_LEN: usize = 64 * 1024 * 1024;
+
+const HEADER_LEN: usize = 6;
+
+/// How the body of a binary file is compressed
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum BinaryCompression {
+    #[default]
+    None,
+    /// Requires the `compression` feature
+    #[cfg(feature = "compression")]
+    Deflate,
+}
+
+impl BinaryCompression {
+    fn to_byte(self) -> u8 {
+        match self {
+            BinaryCompression::None => 0,
+            #[cfg(feature = "compression")]
+            BinaryCompression::Deflate => 1,
+        }
+    }
+}
+
+/// Returns true if `bytes` starts with `magic`
+pub fn has_magic(bytes: &[u8], magic: [u8; 4]) -> bool {
+    bytes.starts_with(&magic)
+}
+
+/// Add the header to `body` and compress it
+pub(crate) fn encode(
+    magic: [u8; 4],
+    body: Vec<u8>,
+    compression: BinaryCompression,
+) -> Result<Vec<u8>, GameUtilError> {
+    let mut output = Vec::with_capacity(HEADER_LEN + body.len());
+    output.extend_from_slice(&magic);
+    output.push(BINARY_VERSION);
+    output.push(compression.to_byte());
+    match compression {
+        BinaryCompression::None => output.extend_from_slice(&body),
+        #[cfg(feature = "compression")]
+        BinaryCompression::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(output, flate2::Compression::default());
+            encoder
+                .write_all(&body)
+                .map_err(|e| GameUtilError::InvalidBinary(e.to_string()))?;
+            output = encoder
+                .finish()
+                .map_err(|e| GameUtilError::InvalidBinary(e.to_string()))?;
+        }
+    }
+    Ok(output)
+}
+
+/// Check the header and return the decompressed body
+pub(crate) fn decode(magic: [u8; 4], bytes: &[u8]) -> Result<Vec<u8>, GameUtilError> {
+    if bytes.len() < HEADER_LEN || !has_magic(bytes, magic) {
+        return Err(GameUtilError::InvalidBinary(format!(
+            "missing header, expected {}",
+            String::from_utf8_lossy(&magic)
+        )));
+    }
+    let version = bytes[4];
+    if version == 0 || version > BINARY_VERSION {
+        return Err(GameUtilError::InvalidBinary(format!(
+            "unsupported version {version}, max is {BINARY_VERSION}"
+        )));
+    }
+    let body = &bytes[HEADER_LEN..];
+    match bytes[5] {
+        0 => Ok(body.to_vec()),
+        #[cfg(feature = "compression")]
+        1 => inflate(body, MAX_DECODED_LEN),
+        #[cfg(not(feature = "compression"))]
+        1 => Err(GameUtilError::InvalidBinary(String::from(
+            "data is compressed, enable the compression feature",
+        ))),
+        other => Err(GameUtilError::InvalidBinary(format!(
+            "unknown compression {other}"
+        ))),
+    }
+}
+
+#[cfg(feature = "compression")]
+fn inflate(body: &[u8], limit: usize) -> Result<Vec<u8>, GameUtilError> {
+    let mut output = vec![];
+    flate2::read::DeflateDecoder::new(body)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|e| GameUtilError::InvalidBinary(e.to_string()))?;
+    if output.len() > limit {
+        return Err(GameUtilError::InvalidBinary(format!(
+            "decompressed data is over {limit} bytes"
+        )));
+    }
+    Ok(output)
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct BinaryWriter {
+    bytes: Vec<u8>,
+}
+
+impl BinaryWriter {
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[cfg(feature = "ici")]
+    pub fn f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn len(&mut self, len: usize) -> Result<(), GameUtilError> {
+        let len = u32::try_from(len)
+            .map_err(|_| GameUtilError::InvalidBinary(format!("{len} is too long")))?;
+        self.u32(len);
+        Ok(())
+    }
+
+    pub fn str(&mut self, value: &str) -> Result<(), GameUtilError> {
+        self.len(value.len())?;
+        self.bytes.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    #[cfg(feature = "ici")]
+    pub fn strs(&mut self, values: &[String]) -> Result<(), GameUtilError> {
+        self.len(values.len())?;
+        for value in values {
+            self.str(value)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the number of runs followed by each run length and value
+    pub fn rle<T: Copy + PartialEq>(
+        &mut self,
+        values: &[T],
+        mut write: impl FnMut(&mut Self, T),
+    ) -> Result<(), GameUtilError> {
+        let mut runs: Vec<(u32, T)> = vec![];
+        for &value in values {
+            match runs.last_mut() {
+                Some((count, last)) if *last == value && *count < u32::MAX => *count += 1,
+                _ => runs.push((1, value)),
+            }
+        }
+        self.len(runs.len())?;
+        for (count, value) in runs {
+            self.u32(count);
+            write(self, value);
+        }
+        Ok(())
+    }
+
+    /// Writes entries sorted by key
+    #[cfg(feature = "ici")]
+    pub fn map<V>(
+        &mut self,
+        map: &HashMap<String, V>,
+        mut write: impl FnMut(&mut Self, &V) -> Result<(), GameUtilError>,
+    ) -> Result<(), GameUtilError> {
+        let mut keys: Vec<&String> = map.keys().collect();
+        keys.sort();
+        self.len(keys.len())?;
+        for key in keys {
+            self.str(key)?;
+            write(self, &map[key])?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Values read by [BinaryReader::rle] so far
+    decoded: usize,
+}
+
+impl<'a> BinaryReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            decoded: 0,
+        }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], GameUtilError> {
+        if self.bytes.len() - self.pos < count {
+            return Err(GameUtilError::InvalidBinary(format!(
+                "unexpected end of data at {}",
+                self.pos
+            )));
+        }
+        let slice = &self.bytes[self.pos..self.pos + count];
+        self.pos += count;
+        Ok(slice)
+    }
+
+    /// Returns an error if there is unread data
+    pub fn finish(self) -> Result<(), GameUtilError> {
+        if self.pos == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(GameUtilError::InvalidBinary(format!(
+                "{} unexpected bytes at end",
+                self.bytes.len() - self.pos
+            )))
+        }
+    }
+
+    pub fn u8(&mut self) -> Result<u8, GameUtilError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, GameUtilError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, GameUtilError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    #[cfg(feature = "ici")]
+    pub fn f64(&mut self) -> Result<f64, GameUtilError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    pub fn len(&mut self) -> Result<usize, GameUtilError> {
+        Ok(self.u32()? as usize)
+    }
+
+    pub fn str(&mut self) -> Result<String, GameUtilError> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|e| GameUtilError::InvalidBinary(e.to_string()))
+    }
+
+    #[cfg(feature = "ici")]
+    pub fn strs(&mut self) -> Result<Vec<String>, GameUtilError> {
+        let len = self.len()?;
+        (0..len).map(|_| self.str()).collect()
+    }
+
+    /// Returns an error if the total of all runs read by this reader goes over [MAX_DECODED_LEN]
+    pub fn rle<T: Copy>(
+        &mut self,
+        mut read: impl FnMut(&mut Self) -> Result<T, GameUtilError>,
+    ) -> Result<Vec<T>, GameUtilError> {
+        let runs = self.len()?;
+        let mut values = vec![];
+        for _ in 0..runs {
+            let count = self.len()?;
+            let value = read(self)?;
+            self.decoded = self
+                .decoded
+                .checked_add(count)
+                .filter(|total| *total <= MAX_DECODED_LEN)
+                .ok_or_else(|| {
+                    GameUtilError::InvalidBinary(format!(
+                        "run length data is over {MAX_DECODED_LEN} values"
+                    ))
+                })?;
+            values.extend(std::iter::repeat_n(value, count));
+        }
+        Ok(values)
+    }
+
+    #[cfg(feature = "ici")]
+    pub fn map<V>(
+        &mut self,
+        mut read: impl FnMut(&mut Self) -> Result<V, GameUtilError>,
+    ) -> Result<HashMap<String, V>, GameUtilError> {
+        let len = self.len()?;
+        let mut map = HashMap::new();
+        for _ in 0..len {
+            let key = self.str()?;
+            map.insert(key, read(self)?);
+        }
+        Ok(map)
+    }
+}
+
+fn invalid_value(name: &str, value: u8) -> GameUtilError {
+    GameUtilError::InvalidBinary(format!("invalid {name}: {value}"))
+}
+
+fn projection_to_byte(projection: Projection) -> u8 {
+    match projection {
+        Projection::Orthogonal => 0,
+        Projection::Isometric(IsoLayout::Diamond) => 1,
+        Projection::Isometric(IsoLayout::Staggered) => 2,
+        Projection::Hexagonal(HexOrientation::Pointy, HexOffset::Odd) => 3,
+        Projection::Hexagonal(HexOrientation::Pointy, HexOffset::Even) => 4,
+        Projection::Hexagonal(HexOrientation::Flat, HexOffset::Odd) => 5,
+        Projection::Hexagonal(HexOrientation::Flat, HexOffset::Even) => 6,
+    }
+}
+
+fn projection_from_byte(value: u8) -> Result<Projection, GameUtilError> {
+    Ok(match value {
+        0 => Projection::Orthogonal,
+        1 => Projection::Isometric(IsoLayout::Diamond),
+        2 => Projection::Isometric(IsoLayout::Staggered),
+        3 => Projection::Hexagonal(HexOrientation::Pointy, HexOffset::Odd),
+        4 => Projection::Hexagonal(HexOrientation::Pointy, HexOffset::Even),
+        5 => Projection::Hexagonal(HexOrientation::Flat, HexOffset::Odd),
+        6 => Projection::Hexagonal(HexOrientation::Flat, HexOffset::Even),
+        _ => return Err(invalid_value("projection", value)),
+    })
+}
+
+fn wrap_to_byte(wrap: WrapMode) -> u8 {
+    match wrap {
+        WrapMode::None => 0,
+        WrapMode::Horizontal => 1,
+        WrapMode::Vertical => 2,
+        WrapMode::Both => 3,
+    }
+}
+
+fn wrap_from_byte(value: u8) -> Result<WrapMode, GameUtilError> {
+    Ok(match value {
+        0 => WrapMode::None,
+        1 => WrapMode::Horizontal,
+        2 => WrapMode::Vertical,
+        3 => WrapMode::Both,
+        _ => return Err(invalid_value("wrap mode", value)),
+    })
+}
+
+#[cfg(feature = "ici")]
+pub(crate) fn write_metadata(
+    writer: &mut BinaryWriter,
+    metadata: &TileMetadata,
+) -> Result<(), GameUtilError> {
+    writer.u32(metadata.default_flags);
+    match metadata.collision {
+        CollisionShape::None => writer.u8(0),
+        CollisionShape::Full => writer.u8(1),
+        CollisionShape::Rect { x, y, w, h } => {
+            writer.u8(2);
+            for value in [x, y, w, h] {
+                writer.u32(value);
+            }
+        }
+    }
+    writer.strs(&metadata.tags)?;
+    match &metadata.animation {
+        None => writer.u8(0),
+        Some(animation) => {
+            writer.u8(1);
+            writer.strs(&animation.frames)?;
+            writer.f64(animation.per_frame);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "ici")]
+pub(crate) fn read_metadata(reader: &mut BinaryReader) -> Result<TileMetadata, GameUtilError> {
+    let default_flags = reader.u32()?;
+    let collision = match reader.u8()? {
+        0 => CollisionShape::None,
+        1 => CollisionShape::Full,
+        2 => CollisionShape::Rect {
+            x: reader.u32()?,
+            y: reader.u32()?,
+            w: reader.u32()?,
+            h: reader.u32()?,
+        },
+        other => return Err(invalid_value("collision shape", other)),
+    };
+    let tags = reader.strs()?;
+    let animation = match reader.u8()? {
+        0 => None,
+        1 => Some(TileAnimation {
+            frames: reader.strs()?,
+            per_frame: reader.f64()?,
+        }),
+        other => return Err(invalid_value("animation", other)),
+    };
+    Ok(TileMetadata {
+        default_flags,
+        collision,
+        tags,
+        animation,
+    })
+}
+
+impl TilemapFile {
+    /// Encode as binary, see [crate::tiles::binary]
+    pub fn to_binary(&self, compression: BinaryCompression) -> Result<Vec<u8>, GameUtilError> {
+        let mut writer = BinaryWriter::default();
+        writer.str(&self.name)?;
+        writer.str(&self.tileset)?;
+        let mut flags: Vec<(&u32, &String)> = self.flags.iter().collect();
+        flags.sort();
+        writer.len(flags.len())?;
+        for (flag, name) in flags {
+            writer.u32(*flag);
+            writer.str(name)?;
+        }
+        writer.len(self.tiles.len())?;
+        for tile in &self.tiles {
+            writer.str(&tile.image)?;
+            writer.u32(tile.flags);
+        }
+        writer.len(self.map.len())?;
+        for row in &self.map {
+            writer.rle(row, BinaryWriter::u16)?;
+        }
+        writer.u32(self.data.start.0);
+        writer.u32(self.data.start.1);
+        writer.len(self.data.exits.len())?;
+        for (x, y, map, target_x, target_y) in &self.data.exits {
+            writer.u32(*x);
+            writer.u32(*y);
+            writer.str(map)?;
+            writer.u32(*target_x);
+            writer.u32(*target_y);
+        }
+        writer.u8(projection_to_byte(self.projection));
+        writer.u8(wrap_to_byte(self.wrap));
+        encode(TILEMAP_MAGIC, writer.into_bytes(), compression)
+    }
+
+    /// Decode from binary, see [crate::tiles::binary]
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidBinary] if `bytes` isn't a valid tilemap
+    pub fn from_binary(bytes: &[u8]) -> Result<TilemapFile, GameUtilError> {
+        let body = decode(TILEMAP_MAGIC, bytes)?;
+        let mut reader = BinaryReader::new(&body);
+        let name = reader.str()?;
+        let tileset = reader.str()?;
+        let mut flags = HashMap::new();
+        for _ in 0..reader.len()? {
+            let flag = reader.u32()?;
+            flags.insert(flag, reader.str()?);
+        }
+        let mut tiles = vec![];
+        for _ in 0..reader.len()? {
+            tiles.push(TileDescriptor {
+                image: reader.str()?,
+                flags: reader.u32()?,
+            });
+        }
+        let mut map = vec![];
+        for _ in 0..reader.len()? {
+            map.push(reader.rle(BinaryReader::u16)?);
+        }
+        let start = (reader.u32()?, reader.u32()?);
+        let mut exits = vec![];
+        for _ in 0..reader.len()? {
+            exits.push((
+                reader.u32()?,
+                reader.u32()?,
+                reader.str()?,
+                reader.u32()?,
+                reader.u32()?,
+            ));
+        }
+        let projection = projection_from_byte(reader.u8()?)?;
+        let wrap = wrap_from_byte(reader.u8()?)?;
+        reader.finish()?;
+        Ok(TilemapFile {
+            name,
+            tileset,
+            flags,
+            tiles,
+            map,
+            data: TilemapDataDescriptor { start, exits },
+            projection,
+            wrap,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> TilemapFile {
+        TilemapFile {
+            name: String::from("Desert Temple"),
+            tileset: String::from("desert"),
+            flags: HashMap::from([(1, String::from("wall")), (2, String::from("trap"))]),
+            tiles: vec![
+                TileDescriptor {
+                    image: String::from("sand"),
+                    flags: 0,
+                },
+                TileDescriptor {
+                    image: String::from("temple_wall"),
+                    flags: 1,
+                },
+            ],
+            map: vec![vec![1; 100], vec![1, 0, 0, 0, 1], vec![]],
+            data: TilemapDataDescriptor {
+                start: (1, 1),
+                exits: vec![(1, 3, String::from("desert"), 4, 5)],
+            },
+            projection: Projection::Hexagonal(HexOrientation::Flat, HexOffset::Even),
+            wrap: WrapMode::Vertical,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let file = sample();
+        let bytes = file.to_binary(BinaryCompression::None).unwrap();
+        assert_eq!(&bytes[..6], b"SGUM\x01\x00");
+        assert_eq!(TilemapFile::from_binary(&bytes).unwrap(), file);
+        //the 100 tile row is stored as one run
+        let ron = ron::to_string(&file).unwrap();
+        assert!(bytes.len() < ron.len() / 2);
+    }
+
+    #[test]
+    fn rle() {
+        let mut writer = BinaryWriter::default();
+        writer.rle(&[5_u8, 5, 5, 2, 5], BinaryWriter::u8).unwrap();
+        let bytes = writer.into_bytes();
+        assert_eq!(
+            bytes,
+            vec![3, 0, 0, 0, 3, 0, 0, 0, 5, 1, 0, 0, 0, 2, 1, 0, 0, 0, 5]
+        );
+        let mut reader = BinaryReader::new(&bytes);
+        assert_eq!(reader.rle(BinaryReader::u8).unwrap(), vec![5, 5, 5, 2, 5]);
+        reader.finish().unwrap();
+    }
+
+    #[test]
+    fn huge_run() {
+        let mut writer = BinaryWriter::default();
+        writer.len(1).unwrap();
+        writer.u32(u32::MAX);
+        writer.u8(7);
+        let bytes = writer.into_bytes();
+        assert!(matches!(
+            BinaryReader::new(&bytes).rle(BinaryReader::u8),
+            Err(GameUtilError::InvalidBinary(_))
+        ));
+
+        //the limit is for all runs, not each one
+        let half = u32::try_from(MAX_DECODED_LEN / 2 + 1).unwrap();
+        let mut writer = BinaryWriter::default();
+        for _ in 0..2 {
+            writer.len(1).unwrap();
+            writer.u32(half);
+            writer.u8(7);
+        }
+        let bytes = writer.into_bytes();
+        let mut reader = BinaryReader::new(&bytes);
+        assert_eq!(reader.rle(BinaryReader::u8).unwrap().len(), half as usize);
+        assert!(reader.rle(BinaryReader::u8).is_err());
+
+        let mut file = TILEMAP_MAGIC.to_vec();
+        file.extend_from_slice(&[BINARY_VERSION, 0]);
+        //empty name, tileset, flags and tiles then one row with one run
+        for value in [0, 0, 0, 0, 1, 1] {
+            file.extend_from_slice(&u32::to_le_bytes(value));
+        }
+        file.extend_from_slice(&u32::MAX.to_le_bytes());
+        file.extend_from_slice(&[0, 0]);
+        assert!(matches!(
+            TilemapFile::from_binary(&file),
+            Err(GameUtilError::InvalidBinary(_))
+        ));
+    }
+
+    #[test]
+    fn invalid() {
+        let bytes = sample().to_binary(BinaryCompression::None).unwrap();
+        assert!(TilemapFile::from_binary(&bytes[..bytes.len() - 1]).is_err());
+        assert!(TilemapFile::from_binary(b"SGUT\x01\x00").is_err());
+        let mut newer = bytes.clone();
+        newer[4] = BINARY_VERSION + 1;
+        assert!(matches!(
+            TilemapFile::from_binary(&newer),
+            Err(GameUtilError::InvalidBinary(_))
+        ));
+        let mut extra = bytes;
+        extra.push(0);
+        assert!(TilemapFile::from_binary(&extra).is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed() {
+        let file = sample();
+        let bytes = file.to_binary(BinaryCompression::Deflate).unwrap();
+        assert_eq!(bytes[5], 1);
+        assert_eq!(TilemapFile::from_binary(&bytes).unwrap(), file);
+
+        let body = vec![0; 1000];
+        let bytes = encode(TILEMAP_MAGIC, body.clone(), BinaryCompression::Deflate).unwrap();
+        assert_eq!(inflate(&bytes[HEADER_LEN..], 1000).unwrap(), body);
+        assert!(matches!(
+            inflate(&bytes[HEADER_LEN..], 999),
+            Err(GameUtilError::InvalidBinary(_))
+        ));
+    }
+}
@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use crate::prelude::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct TilemapFile {
     pub name: String,
     ///recommended tileset for this map
@@ -13,11 +13,19 @@ pub struct TilemapFile {
     pub tiles: Vec<TileDescriptor>,
     pub map: Vec<Vec<u16>>,
     pub data: TilemapDataDescriptor,
+    ///how tiles are laid out, defaults to orthogonal
+    #[serde(default)]
+    pub projection: Projection,
+    ///which edges connect to the opposite edge, defaults to none
+    #[serde(default)]
+    pub wrap: WrapMode,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct TileDescriptor {
     pub image: String,
+    ///combined with the tileset default flags for the image
+    #[serde(default)]
     pub flags: u32,
 }
 
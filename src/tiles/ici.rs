@@ -1,17 +1,50 @@
 use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
 use std::rc::Rc;
 
 use ici_files::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::prelude::*;
+use crate::tiles::binary::{
+    decode, encode, read_metadata, write_metadata, BinaryReader, BinaryWriter,
+};
 
-#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IciTileset {
     pub name: String,
     tile_size: (u8, u8),
     palette: Vec<Color>,
     tiles: HashMap<String, Vec<u8>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    animated: HashMap<String, IciAnimation>,
+    /// Alternate palettes, see [IciTileset::palette_variant]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    palettes: HashMap<String, Vec<Color>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cycles: Vec<PaletteCycle>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, TileMetadata>,
+    /// Alias to tile name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    aliases: HashMap<String, String>,
+}
+
+/// Frames for an animated tile, played on a loop
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IciAnimation {
+    /// Seconds each frame is shown for
+    pub per_frame: f64,
+    /// Pixels for each frame
+    pub frames: Vec<Vec<u8>>,
+}
+
+impl IciAnimation {
+    pub fn new(per_frame: f64, frames: Vec<Vec<u8>>) -> Self {
+        Self { per_frame, frames }
+    }
 }
 
 impl IciTileset {
@@ -26,17 +59,191 @@ impl IciTileset {
             tile_size,
             palette,
             tiles,
+            animated: HashMap::new(),
+            palettes: HashMap::new(),
+            cycles: vec![],
+            metadata: HashMap::new(),
+            aliases: HashMap::new(),
+        }
+    }
+}
+
+impl IciTileset {
+    /// Create a tileset from all `.ici` and `.ica` files in `dir`
+    ///
+    /// Tiles are named after the file (without extension) and added in file name order,
+    /// `.ica` files are added as animated tiles.
+    /// Images must include their palette colors and all be the same size, palettes are merged
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::FileNotFound] or [GameUtilError::ReadingFile] if the directory or a file can't be read
+    /// [GameUtilError::InvalidImage] if a file is not a valid image or the wrong size
+    /// [GameUtilError::TooManyColors] if the combined palette would have more than 256 colors
+    pub fn from_dir<P: AsRef<Path>>(name: &str, dir: P) -> Result<Self, GameUtilError> {
+        let dir = dir.as_ref();
+        let mut paths = vec![];
+        for entry in fs::read_dir(dir).map_err(|e| GameUtilError::from_io(dir, e))? {
+            let path = entry.map_err(|e| GameUtilError::from_io(dir, e))?.path();
+            let ext = path.extension().and_then(|ext| ext.to_str());
+            if path.is_file() && matches!(ext, Some("ici") | Some("ica")) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut tileset: Option<IciTileset> = None;
+        for path in paths {
+            let bytes = fs::read(&path).map_err(|e| GameUtilError::from_io(&path, e))?;
+            let invalid = |e: IndexedImageError| {
+                GameUtilError::InvalidImage(format!("{}: {e}", path.display()))
+            };
+            let (image, palette): (IndexedWrapper, FilePalette) =
+                if path.extension().and_then(|ext| ext.to_str()) == Some("ica") {
+                    let (image, palette) =
+                        AnimatedIndexedImage::from_file_contents(&bytes).map_err(invalid)?;
+                    (image.into(), palette)
+                } else {
+                    let (image, palette) =
+                        IndexedImage::from_file_contents(&bytes).map_err(invalid)?;
+                    (image.into(), palette)
+                };
+            if palette != FilePalette::Colors {
+                return Err(GameUtilError::InvalidImage(format!(
+                    "{} doesn't include palette colors",
+                    path.display()
+                )));
+            }
+            let tile_name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let tileset = tileset.get_or_insert_with(|| {
+                IciTileset::new(name.to_string(), image.size(), vec![], HashMap::new())
+            });
+            match image {
+                IndexedWrapper::Static(image) => tileset.try_add_image(&tile_name, &image)?,
+                IndexedWrapper::Animated(image) => tileset.try_add_animated(&tile_name, &image)?,
+            }
         }
+
+        Ok(tileset
+            .unwrap_or_else(|| IciTileset::new(name.to_string(), (0, 0), vec![], HashMap::new())))
+    }
+
+    /// Create a tileset by slicing `image` using `layout`
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidAtlas] if the layout doesn't fit the image or has no tiles, or
+    /// the rects aren't the same size
+    pub fn from_sprite_sheet(
+        name: &str,
+        image: &IndexedImage,
+        layout: &AtlasLayout,
+    ) -> Result<Self, GameUtilError> {
+        let tileset = Tileset::from_atlas(image, layout)?;
+        let (w, h) = tileset.tilesize();
+        let mut output =
+            IciTileset::new(name.to_string(), (w as u8, h as u8), vec![], HashMap::new());
+        for (name, image) in tileset.names().iter().zip(tileset.images()) {
+            output.try_add_image(name, image)?;
+        }
+        Ok(output)
     }
 }
 
 impl IciTileset {
+    /// Add or replace a tile
+    ///
+    /// Colors used by `image` that aren't in the tileset palette are added to it
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidImage] if `image` isn't the tile size
+    /// [GameUtilError::TooManyColors] if the palette would have more than 256 colors
+    pub fn try_add_image(&mut self, name: &str, image: &IndexedImage) -> Result<(), GameUtilError> {
+        self.check_size(name, image.size())?;
+        let pixels = self.merge_palette(image.get_palette(), image.get_pixels())?;
+        self.animated.remove(name);
+        self.tiles.insert(name.to_string(), pixels);
+        Ok(())
+    }
+
+    /// Add or replace an animated tile, it will always loop
+    ///
+    /// Colors used by `image` that aren't in the tileset palette are added to it
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidImage] if `image` isn't the tile size
+    /// [GameUtilError::TooManyColors] if the palette would have more than 256 colors
+    pub fn try_add_animated(
+        &mut self,
+        name: &str,
+        image: &AnimatedIndexedImage,
+    ) -> Result<(), GameUtilError> {
+        self.check_size(name, image.size())?;
+        let pixels = self.merge_palette(image.get_palette(), image.get_pixels())?;
+        let frame_size = image.width() as usize * image.height() as usize;
+        let frames = pixels.chunks(frame_size).map(|f| f.to_vec()).collect();
+        self.tiles.remove(name);
+        self.animated.insert(
+            name.to_string(),
+            IciAnimation::new(image.get_per_frame(), frames),
+        );
+        Ok(())
+    }
+
+    fn check_size(&self, name: &str, size: (u8, u8)) -> Result<(), GameUtilError> {
+        if size != self.tile_size {
+            return Err(GameUtilError::InvalidImage(format!(
+                "{name} is {}x{} but tiles are {}x{}",
+                size.0, size.1, self.tile_size.0, self.tile_size.1
+            )));
+        }
+        Ok(())
+    }
+
+    /// Adds any colors used in `pixels` to the tileset palette and returns `pixels` remapped to it
+    fn merge_palette(&mut self, colors: &[Color], pixels: &[u8]) -> Result<Vec<u8>, GameUtilError> {
+        let mut palette = self.palette.clone();
+        let mut remap: HashMap<u8, u8> = HashMap::new();
+        let mut output = Vec::with_capacity(pixels.len());
+        for &pixel in pixels {
+            let idx = match remap.get(&pixel) {
+                Some(idx) => *idx,
+                None => {
+                    let color = colors[pixel as usize];
+                    let idx = match palette.iter().position(|c| *c == color) {
+                        Some(idx) => idx,
+                        None => {
+                            palette.push(color);
+                            palette.len() - 1
+                        }
+                    };
+                    if idx > u8::MAX as usize {
+                        let extra = colors.iter().filter(|c| !palette.contains(c)).count();
+                        return Err(GameUtilError::TooManyColors(palette.len() + extra));
+                    }
+                    remap.insert(pixel, idx as u8);
+                    idx as u8
+                }
+            };
+            output.push(idx);
+        }
+        self.palette = palette;
+        Ok(output)
+    }
+
+    /// Add or replace a tile
+    ///
+    /// # Panics
+    ///
+    /// See [IciTileset::try_add_image]
     pub fn add_image(&mut self, name: &str, image: &IndexedImage) {
-        if image.get_palette() == self.palette {
-            self.tiles
-                .insert(name.to_string(), image.get_pixels().to_vec());
-        } else {
-            panic!("Image has invalid palette");
+        if let Err(e) = self.try_add_image(name, image) {
+            panic!("{e}");
         }
     }
 
@@ -51,16 +258,104 @@ impl IciTileset {
     pub fn tiles(&self) -> &HashMap<String, Vec<u8>> {
         &self.tiles
     }
+
+    pub fn animated(&self) -> &HashMap<String, IciAnimation> {
+        &self.animated
+    }
+
+    pub fn palettes(&self) -> &HashMap<String, Vec<Color>> {
+        &self.palettes
+    }
+
+    pub fn cycles(&self) -> &[PaletteCycle] {
+        &self.cycles
+    }
+
+    pub fn metadata(&self) -> &HashMap<String, TileMetadata> {
+        &self.metadata
+    }
+
+    pub fn aliases(&self) -> &HashMap<String, String> {
+        &self.aliases
+    }
+}
+
+impl IciTileset {
+    /// Set metadata for tile `name`, it's applied when converting into a [Tileset]
+    pub fn set_metadata(&mut self, name: &str, metadata: TileMetadata) {
+        self.metadata.insert(name.to_string(), metadata);
+    }
+
+    /// Add `alias` as another name for tile `name`, it's applied when converting into a [Tileset]
+    pub fn add_alias(&mut self, alias: &str, name: &str) {
+        self.aliases.insert(alias.to_string(), name.to_string());
+    }
+
+    fn apply_extras<Image: Debug + Clone>(
+        tileset: &mut Tileset<Image>,
+        metadata: HashMap<String, TileMetadata>,
+        aliases: HashMap<String, String>,
+    ) {
+        for (name, metadata) in metadata {
+            tileset.set_metadata(&name, metadata);
+        }
+        let mut aliases: Vec<(String, String)> = aliases.into_iter().collect();
+        aliases.sort();
+        for (alias, name) in aliases {
+            tileset.add_alias(&alias, &name);
+        }
+    }
 }
 
 impl IciTileset {
+    /// Add or replace an alternate palette
+    ///
+    /// It can have fewer colors than [IciTileset::palette], see [IciTileset::palette_variant]
+    pub fn add_palette(&mut self, name: &str, colors: Vec<Color>) {
+        self.palettes.insert(name.to_string(), colors);
+    }
+
+    /// Returns the alternate palette `name`, any colors it's missing are taken from [IciTileset::palette]
+    pub fn palette_variant(&self, name: &str) -> Option<Vec<Color>> {
+        self.palettes.get(name).map(|colors| {
+            let mut palette = self.palette.clone();
+            for (i, color) in colors.iter().enumerate().take(palette.len()) {
+                palette[i] = *color;
+            }
+            palette
+        })
+    }
+
+    pub fn add_cycle(&mut self, cycle: PaletteCycle) {
+        self.cycles.push(cycle);
+    }
+
+    /// Create an animator for this tileset's palette and cycles
+    pub fn palette_animator(&self) -> PaletteAnimator {
+        PaletteAnimator::new(self.palette.clone(), self.cycles.clone())
+    }
+}
+
+impl IciTileset {
+    /// Convert into a tileset of static images, animated tiles only use their first frame
+    ///
+    /// Tiles are sorted by name
+    ///
+    /// See [IciTileset::into_animated_tileset]
     pub fn into_tileset(self) -> Result<Tileset<IndexedImage>, String> {
         let mut images = vec![];
         let mut names = vec![];
 
         let palette = self.palette;
 
-        for (name, pixels) in self.tiles {
+        let first_frames = self
+            .animated
+            .into_iter()
+            .filter_map(|(name, anim)| anim.frames.into_iter().next().map(|pixels| (name, pixels)));
+        let mut tiles: Vec<(String, Vec<u8>)> =
+            self.tiles.into_iter().chain(first_frames).collect();
+        tiles.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+        for (name, pixels) in tiles {
             names.push(name);
             images.push(Rc::new(
                 IndexedImage::new(self.tile_size.0, self.tile_size.1, palette.clone(), pixels)
@@ -68,11 +363,159 @@ impl IciTileset {
             ));
         }
 
-        Ok(Tileset::new(
+        let mut tileset = Tileset::new(
             images,
             names,
             (self.tile_size.0 as u32, self.tile_size.1 as u32),
-        ))
+        );
+        IciTileset::apply_extras(&mut tileset, self.metadata, self.aliases);
+        Ok(tileset)
+    }
+
+    /// Convert into a tileset where animated tiles can be advanced with [Tilemap::update]
+    ///
+    /// Tiles are sorted by name
+    pub fn into_animated_tileset(self) -> Result<Tileset<IndexedWrapper>, String> {
+        let (w, h) = self.tile_size;
+        let palette = self.palette;
+
+        let mut tiles = vec![];
+        for (name, pixels) in self.tiles {
+            let image =
+                IndexedImage::new(w, h, palette.clone(), pixels).map_err(|e| e.to_string())?;
+            tiles.push((name, IndexedWrapper::Static(image)));
+        }
+        for (name, anim) in self.animated {
+            let frame_count = u8::try_from(anim.frames.len())
+                .map_err(|_| format!("{name} has {} frames, max is 255", anim.frames.len()))?;
+            let image = AnimatedIndexedImage::new(
+                w,
+                h,
+                anim.per_frame,
+                frame_count,
+                palette.clone(),
+                anim.frames.concat(),
+                PlayType::Loops,
+            )
+            .map_err(|e| e.to_string())?;
+            tiles.push((name, IndexedWrapper::Animated(image)));
+        }
+        tiles.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+
+        let (names, images) = tiles
+            .into_iter()
+            .map(|(name, image)| (name, Rc::new(image)))
+            .unzip();
+        let mut tileset = Tileset::new(images, names, (w as u32, h as u32));
+        IciTileset::apply_extras(&mut tileset, self.metadata, self.aliases);
+        Ok(tileset)
+    }
+}
+
+fn write_colors(writer: &mut BinaryWriter, colors: &[Color]) -> Result<(), GameUtilError> {
+    writer.len(colors.len())?;
+    for color in colors {
+        for channel in [color.r, color.g, color.b, color.a] {
+            writer.u8(channel);
+        }
+    }
+    Ok(())
+}
+
+fn read_colors(reader: &mut BinaryReader) -> Result<Vec<Color>, GameUtilError> {
+    let len = reader.len()?;
+    (0..len)
+        .map(|_| {
+            Ok(Color::new(
+                reader.u8()?,
+                reader.u8()?,
+                reader.u8()?,
+                reader.u8()?,
+            ))
+        })
+        .collect()
+}
+
+impl IciTileset {
+    /// Encode as binary, see [crate::tiles::binary]
+    pub fn to_binary(&self, compression: BinaryCompression) -> Result<Vec<u8>, GameUtilError> {
+        let mut writer = BinaryWriter::default();
+        writer.str(&self.name)?;
+        writer.u8(self.tile_size.0);
+        writer.u8(self.tile_size.1);
+        write_colors(&mut writer, &self.palette)?;
+        writer.map(&self.tiles, |w, pixels| w.rle(pixels, BinaryWriter::u8))?;
+        writer.map(&self.animated, |w, anim| {
+            w.f64(anim.per_frame);
+            w.len(anim.frames.len())?;
+            for frame in &anim.frames {
+                w.rle(frame, BinaryWriter::u8)?;
+            }
+            Ok(())
+        })?;
+        writer.map(&self.palettes, |w, colors| write_colors(w, colors))?;
+        writer.len(self.cycles.len())?;
+        for cycle in &self.cycles {
+            writer.u8(cycle.start);
+            writer.u8(cycle.end);
+            writer.f64(cycle.per_step);
+        }
+        writer.map(&self.metadata, write_metadata)?;
+        writer.map(&self.aliases, |w, name| w.str(name))?;
+        encode(TILESET_MAGIC, writer.into_bytes(), compression)
+    }
+
+    /// Decode from binary, see [crate::tiles::binary]
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidBinary] if `bytes` isn't a valid tileset
+    pub fn from_binary(bytes: &[u8]) -> Result<IciTileset, GameUtilError> {
+        let body = decode(TILESET_MAGIC, bytes)?;
+        let mut reader = BinaryReader::new(&body);
+        let name = reader.str()?;
+        let tile_size = (reader.u8()?, reader.u8()?);
+        let palette = read_colors(&mut reader)?;
+        let tiles = reader.map(|r| r.rle(BinaryReader::u8))?;
+        let animated = reader.map(|r| {
+            let per_frame = r.f64()?;
+            let len = r.len()?;
+            let frames = (0..len)
+                .map(|_| r.rle(BinaryReader::u8))
+                .collect::<Result<_, _>>()?;
+            Ok(IciAnimation::new(per_frame, frames))
+        })?;
+        let palettes = reader.map(read_colors)?;
+        let mut cycles = vec![];
+        for _ in 0..reader.len()? {
+            cycles.push(PaletteCycle::new(reader.u8()?, reader.u8()?, reader.f64()?));
+        }
+        let metadata = reader.map(read_metadata)?;
+        let aliases = reader.map(|r| r.str())?;
+        reader.finish()?;
+        Ok(IciTileset {
+            name,
+            tile_size,
+            palette,
+            tiles,
+            animated,
+            palettes,
+            cycles,
+            metadata,
+            aliases,
+        })
+    }
+}
+
+impl AnimatedImage for IndexedWrapper {
+    fn update(&mut self, delta: f64) {
+        IndexedWrapper::update(self, delta)
+    }
+}
+
+impl AnimatedImage for AnimatedIndexedImage {
+    fn update(&mut self, delta: f64) {
+        AnimatedIndexedImage::update(self, delta)
     }
 }
 
@@ -118,6 +561,7 @@ mod test {
             .unwrap();
         assert_eq!(tileset.tilesize(), (3, 3));
         assert_eq!(tileset.images().len(), 4);
+        assert_eq!(tileset.names(), &["blue", "green", "red", "trans"]);
         assert_eq!(tileset.find_by_name("red").unwrap().get_palette()[3], BLUE);
         assert_eq!(tileset.find_by_name("blue").unwrap().get_palette()[1], RED);
         assert!(tileset
@@ -133,4 +577,271 @@ mod test {
             .iter()
             .all(|&p| p == 2));
     }
+
+    #[test]
+    fn adding_merges_palettes() {
+        let mut tileset: IciTileset = ron::from_str(SAMPLE_RON).unwrap();
+        let white = Color::new(255, 255, 255, 255);
+        let image =
+            IndexedImage::new(3, 3, vec![white, BLUE], vec![0, 1, 0, 1, 0, 1, 0, 1, 0]).unwrap();
+        tileset.try_add_image("check", &image).unwrap();
+        assert_eq!(tileset.palette, vec![TRANSPARENT, RED, GREEN, BLUE, white]);
+        assert_eq!(tileset.tiles["check"], vec![4, 3, 4, 3, 4, 3, 4, 3, 4]);
+
+        let wrong_size = IndexedImage::new(1, 1, vec![white], vec![0]).unwrap();
+        assert!(matches!(
+            tileset.try_add_image("small", &wrong_size),
+            Err(GameUtilError::InvalidImage(_))
+        ));
+
+        let palette: Vec<Color> = (0..=255).map(|i| Color::new(i, 1, 1, 255)).collect();
+        let pixels: Vec<u8> = (0..=255).collect();
+        let mut full = IciTileset::new(String::new(), (16, 16), vec![], HashMap::new());
+        let image = IndexedImage::new(16, 16, palette, pixels).unwrap();
+        full.try_add_image("all", &image).unwrap();
+        assert_eq!(full.palette.len(), 256);
+        assert!(matches!(
+            full.try_add_image(
+                "more",
+                &IndexedImage::new(16, 16, vec![RED], vec![0; 256]).unwrap()
+            ),
+            Err(GameUtilError::TooManyColors(257))
+        ));
+    }
+
+    #[test]
+    fn from_sprite_sheet() {
+        #[rustfmt::skip]
+        let sheet = IndexedImage::new(4, 2, vec![RED, GREEN], vec![
+            0, 0, 1, 1,
+            0, 0, 1, 0,
+        ]).unwrap();
+        let layout = AtlasLayout::grid((2, 2));
+        let tileset = IciTileset::from_sprite_sheet("sheet", &sheet, &layout).unwrap();
+        assert_eq!(tileset.tile_size, (2, 2));
+        assert_eq!(tileset.palette, vec![RED, GREEN]);
+        assert_eq!(tileset.tiles["0"], vec![0; 4]);
+        assert_eq!(tileset.tiles["1"], vec![1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn from_dir() {
+        let dir = std::env::temp_dir().join(format!("sgu_ici_tileset_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, palette: Vec<Color>, pixels: Vec<u8>| {
+            let image = IndexedImage::new(2, 1, palette, pixels).unwrap();
+            fs::write(
+                dir.join(name),
+                image.to_file_contents(&FilePalette::Colors).unwrap(),
+            )
+            .unwrap();
+        };
+        write("b.ici", vec![GREEN, RED], vec![0, 1]);
+        write("a.ici", vec![RED], vec![0, 0]);
+        let anim = AnimatedIndexedImage::new(
+            2,
+            1,
+            0.5,
+            2,
+            vec![BLUE, RED],
+            vec![0, 0, 1, 1],
+            PlayType::Loops,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("c.ica"),
+            anim.to_file_contents(&FilePalette::Colors).unwrap(),
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not an image").unwrap();
+
+        let tileset = IciTileset::from_dir("dir", &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(tileset.tile_size, (2, 1));
+        assert_eq!(tileset.palette, vec![RED, GREEN, BLUE]);
+        assert_eq!(tileset.tiles["a"], vec![0, 0]);
+        assert_eq!(tileset.tiles["b"], vec![1, 0]);
+        assert_eq!(
+            tileset.animated["c"],
+            IciAnimation::new(0.5, vec![vec![2, 2], vec![0, 0]])
+        );
+
+        assert!(matches!(
+            IciTileset::from_dir("missing", dir),
+            Err(GameUtilError::FileNotFound(_))
+        ));
+    }
+
+    const ANIMATED_RON: &str = r#"(
+        name: "animated",
+        tile_size: (1,1),
+        palette: [4278190335,16711935],
+        tiles: {
+            "red": [0],
+        },
+        animated: {
+            "flash": (
+                per_frame: 0.5,
+                frames: [[0],[1]],
+            ),
+        }
+    )
+    "#;
+
+    #[test]
+    fn animated() {
+        let tileset: IciTileset = ron::from_str(ANIMATED_RON).unwrap();
+        assert_eq!(
+            tileset.animated,
+            HashMap::from([(
+                "flash".to_string(),
+                IciAnimation::new(0.5, vec![vec![0], vec![1]])
+            )])
+        );
+        let round_trip: IciTileset = ron::from_str(&ron::to_string(&tileset).unwrap()).unwrap();
+        assert_eq!(round_trip, tileset);
+        assert!(
+            !ron::to_string(&ron::from_str::<IciTileset>(SAMPLE_RON).unwrap())
+                .unwrap()
+                .contains("animated")
+        );
+
+        let tileset = tileset.into_animated_tileset().unwrap();
+        let mut tilemap = Tilemap::new(
+            vec![0, 1],
+            vec![0, 0],
+            MapSize::new(2, 1),
+            vec![String::from("red"), String::from("flash")],
+            tileset,
+            (2, 1),
+            MapPosition::new(0, 0),
+            vec![],
+        )
+        .unwrap();
+        let colors = |tilemap: &Tilemap<IndexedWrapper>| {
+            let mut colors = vec![];
+            tilemap.draw(|img, _| {
+                let pixels = match img {
+                    IndexedWrapper::Static(img) => img.get_pixels(),
+                    IndexedWrapper::Animated(img) => img.get_current_frame_pixels(),
+                };
+                colors.push(img.get_color(pixels[0]).unwrap())
+            });
+            colors
+        };
+        assert_eq!(colors(&tilemap), vec![RED, RED]);
+        //frames change on the update after the frame time has passed
+        tilemap.update_secs(0.6);
+        tilemap.update_secs(0.0);
+        assert_eq!(colors(&tilemap), vec![RED, GREEN]);
+        tilemap.update_secs(0.6);
+        tilemap.update_secs(0.0);
+        assert_eq!(colors(&tilemap), vec![RED, RED]);
+
+        let tileset = ron::from_str::<IciTileset>(ANIMATED_RON)
+            .unwrap()
+            .into_tileset()
+            .unwrap();
+        assert_eq!(tileset.find_by_name("flash").unwrap().get_pixels(), &[0]);
+
+        let mut tileset = ron::from_str::<IciTileset>(ANIMATED_RON).unwrap();
+        tileset.animated.insert(
+            String::from("long"),
+            IciAnimation::new(0.5, vec![vec![0]; 256]),
+        );
+        assert_eq!(
+            tileset.into_animated_tileset().unwrap_err(),
+            "long has 256 frames, max is 255"
+        );
+    }
+
+    #[test]
+    fn palettes() {
+        let mut tileset: IciTileset = ron::from_str(
+            r#"(
+            name: "palettes",
+            tile_size: (1,1),
+            palette: [0,4278190335,16711935,65535],
+            tiles: {},
+            palettes: {
+                "night": [0,255],
+            },
+            cycles: [(start: 1, end: 3, per_step: 0.5)],
+        )"#,
+        )
+        .unwrap();
+        let black = Color::new(0, 0, 0, 255);
+        assert_eq!(
+            tileset.palette_variant("night"),
+            Some(vec![TRANSPARENT, black, GREEN, BLUE])
+        );
+        assert_eq!(tileset.palette_variant("day"), None);
+        tileset.add_palette("day", vec![RED]);
+        assert_eq!(
+            tileset.palette_variant("day"),
+            Some(vec![RED, RED, GREEN, BLUE])
+        );
+        let mut animator = tileset.palette_animator();
+        assert!(animator.update_secs(0.5));
+        assert_eq!(animator.palette(), &[TRANSPARENT, BLUE, RED, GREEN]);
+    }
+
+    #[test]
+    fn metadata_and_aliases() {
+        let mut tileset: IciTileset = ron::from_str(
+            r#"(
+            name: "meta",
+            tile_size: (1,1),
+            palette: [4278190335],
+            tiles: {
+                "lava": [0],
+            },
+            metadata: {
+                "lava": (default_flags: 4, collision: Full, tags: ["hot"]),
+            },
+            aliases: {
+                "magma": "lava",
+            },
+        )"#,
+        )
+        .unwrap();
+        tileset.add_alias("fire", "lava");
+        let tileset = tileset.into_tileset().unwrap();
+        assert_eq!(tileset.find_by_name("magma"), tileset.find_by_name("lava"));
+        assert_eq!(tileset.id_of("fire"), Some(TileId(0)));
+        let meta = tileset.metadata_by_name("magma").unwrap();
+        assert_eq!(meta.default_flags, 4);
+        assert_eq!(meta.collision, CollisionShape::Full);
+        assert_eq!(meta.tags, vec![String::from("hot")]);
+    }
+
+    #[test]
+    fn binary() {
+        let mut tileset: IciTileset = ron::from_str(ANIMATED_RON).unwrap();
+        tileset.add_palette("dark", vec![BLUE]);
+        tileset.add_cycle(PaletteCycle::new(0, 1, 0.25));
+        tileset.add_alias("blink", "flash");
+        tileset.set_metadata(
+            "red",
+            TileMetadata {
+                default_flags: 3,
+                collision: CollisionShape::Rect {
+                    x: 0,
+                    y: 0,
+                    w: 1,
+                    h: 1,
+                },
+                tags: vec![String::from("solid")],
+                animation: Some(TileAnimation {
+                    frames: vec![String::from("red"), String::from("flash")],
+                    per_frame: 0.1,
+                }),
+            },
+        );
+        let bytes = tileset.to_binary(BinaryCompression::None).unwrap();
+        assert!(has_magic(&bytes, TILESET_MAGIC));
+        assert_eq!(IciTileset::from_binary(&bytes).unwrap(), tileset);
+        assert!(IciTileset::from_binary(&bytes[1..]).is_err());
+        assert!(TilemapFile::from_binary(&bytes).is_err());
+    }
 }
@@ -1,14 +1,28 @@
+pub mod atlas;
+pub mod binary;
 pub mod file;
 #[cfg(feature = "ici")]
 pub mod ici;
+#[cfg(feature = "ici")]
+pub mod palette;
+pub mod projection;
+#[cfg(feature = "ici")]
+pub mod render;
 pub mod tilemap;
 pub mod tileset;
 pub mod units;
 
 pub mod prelude {
+    pub use crate::tiles::atlas::*;
+    pub use crate::tiles::binary::*;
     pub use crate::tiles::file::*;
     #[cfg(feature = "ici")]
     pub use crate::tiles::ici::*;
+    #[cfg(feature = "ici")]
+    pub use crate::tiles::palette::*;
+    pub use crate::tiles::projection::*;
+    #[cfg(feature = "ici")]
+    pub use crate::tiles::render::*;
     pub use crate::tiles::tilemap::*;
     pub use crate::tiles::tileset::*;
     pub use crate::tiles::units::*;
@@ -0,0 +1,274 @@
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use ici_files::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+/// Images that draw using a replaceable palette
+pub trait PaletteImage {
+    /// Replace the palette, `palette` must have at least as many colors as the image uses
+    fn set_palette(&mut self, palette: &[Color]) -> Result<(), IndexedImageError>;
+}
+
+/// `min_palette_size_supported` is the highest index used so the palette must be longer
+fn check_palette_size(highest: u8, palette: &[Color]) -> Result<(), IndexedImageError> {
+    if palette.len() <= highest as usize {
+        return Err(IndexedImageError::PaletteTooFewColors(highest));
+    }
+    Ok(())
+}
+
+impl PaletteImage for IndexedImage {
+    fn set_palette(&mut self, palette: &[Color]) -> Result<(), IndexedImageError> {
+        check_palette_size(self.min_palette_size_supported(), palette)?;
+        IndexedImage::set_palette(self, palette)
+    }
+}
+
+impl PaletteImage for AnimatedIndexedImage {
+    fn set_palette(&mut self, palette: &[Color]) -> Result<(), IndexedImageError> {
+        check_palette_size(self.min_palette_size_supported(), palette)?;
+        AnimatedIndexedImage::set_palette(self, palette)
+    }
+}
+
+impl PaletteImage for IndexedWrapper {
+    fn set_palette(&mut self, palette: &[Color]) -> Result<(), IndexedImageError> {
+        check_palette_size(self.min_palette_size_supported(), palette)?;
+        IndexedWrapper::set_palette(self, palette)
+    }
+}
+
+impl<Image: PaletteImage + Debug + Clone> Tilemap<Image> {
+    /// Replace the palette of every tile image
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::InvalidPalette] if `palette` has fewer colors than an image uses,
+    /// images before the failing one will have been updated
+    pub fn set_palette(&mut self, palette: &[Color]) -> Result<(), GameUtilError> {
+        for image in self.images_mut() {
+            Rc::make_mut(image)
+                .set_palette(palette)
+                .map_err(|e| GameUtilError::InvalidPalette(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Rotates a range of palette colors over time
+///
+/// Each step the colors in `start..=end` move up one index, with the last wrapping to `start`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaletteCycle {
+    /// First palette index in the cycle
+    pub start: u8,
+    /// Last palette index in the cycle (inclusive)
+    pub end: u8,
+    /// Seconds between each step
+    pub per_step: f64,
+}
+
+impl PaletteCycle {
+    pub fn new(start: u8, end: u8, per_step: f64) -> Self {
+        Self {
+            start,
+            end,
+            per_step,
+        }
+    }
+
+    fn apply(&self, base: &[Color], output: &mut [Color], step: usize) {
+        let start = self.start as usize;
+        let end = (self.end as usize).min(base.len().saturating_sub(1));
+        if start >= end {
+            return;
+        }
+        let len = end - start + 1;
+        for i in 0..len {
+            output[start + (i + step) % len] = base[start + i];
+        }
+    }
+}
+
+/// Applies [PaletteCycle]s to a base palette
+///
+/// # Usage
+///
+/// ```
+///# use std::rc::Rc;
+///# use simple_game_utils::prelude::*;
+///# use ici_files::prelude::*;
+///# fn main() {
+///# let timing = Timing::new(60);
+///# let palette: Vec<Color> = (0..8).map(|i| Color::new(i * 30, 0, 0, 255)).collect();
+///# let image = IndexedImage::new(1, 1, palette.clone(), vec![0]).unwrap();
+///# let tileset = Tileset::new(vec![Rc::new(image)], vec![String::from("tile")], (1, 1));
+///# let mut tilemap = Tilemap::new(
+///#     vec![0],
+///#     vec![0],
+///#     MapSize::new(1, 1),
+///#     vec![String::from("tile")],
+///#     tileset,
+///#     (1, 1),
+///#     MapPosition::new(0, 0),
+///#     vec![],
+///# )
+///# .unwrap();
+/// let mut animator = PaletteAnimator::new(palette, vec![PaletteCycle::new(4, 7, 0.25)]);
+/// if animator.update(&timing) {
+///     tilemap.set_palette(animator.palette()).unwrap();
+/// }
+///# }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteAnimator {
+    base: Vec<Color>,
+    palette: Vec<Color>,
+    cycles: Vec<CycleState>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CycleState {
+    cycle: PaletteCycle,
+    step: usize,
+    timer: Timer,
+}
+
+impl PaletteAnimator {
+    pub fn new(base: Vec<Color>, cycles: Vec<PaletteCycle>) -> Self {
+        let cycles = cycles
+            .into_iter()
+            .map(|cycle| CycleState {
+                timer: Timer::new_with_delay(cycle.per_step, cycle.per_step),
+                cycle,
+                step: 0,
+            })
+            .collect();
+        Self {
+            palette: base.clone(),
+            base,
+            cycles,
+        }
+    }
+}
+
+impl PaletteAnimator {
+    /// Update cycles, returns true if the palette changed
+    #[inline]
+    pub fn update(&mut self, timing: &Timing) -> bool {
+        self.update_secs(timing.fixed_time_step)
+    }
+
+    /// Update cycles, returns true if the palette changed
+    /// `delta` is fractional seconds passed since last call
+    pub fn update_secs(&mut self, delta: f64) -> bool {
+        let mut changed = false;
+        for state in &mut self.cycles {
+            if state.timer.update_secs(delta) {
+                state.step += 1;
+                changed = true;
+            }
+        }
+        if changed {
+            self.palette.copy_from_slice(&self.base);
+            for state in &self.cycles {
+                state.cycle.apply(&self.base, &mut self.palette, state.step);
+            }
+        }
+        changed
+    }
+
+    /// Restart all cycles and restore the base palette
+    pub fn reset(&mut self) {
+        for state in &mut self.cycles {
+            state.step = 0;
+            state.timer.reset();
+        }
+        self.palette.copy_from_slice(&self.base);
+    }
+
+    /// Replace the base palette, keeping cycle progress
+    pub fn set_base(&mut self, base: Vec<Color>) {
+        self.palette = base.clone();
+        self.base = base;
+        for state in &self.cycles {
+            state.cycle.apply(&self.base, &mut self.palette, state.step);
+        }
+    }
+
+    /// The current palette
+    #[inline]
+    pub fn palette(&self) -> &[Color] {
+        &self.palette
+    }
+
+    #[inline]
+    pub fn base(&self) -> &[Color] {
+        &self.base
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const C0: Color = Color::new(0, 0, 0, 255);
+    const C1: Color = Color::new(1, 0, 0, 255);
+    const C2: Color = Color::new(2, 0, 0, 255);
+    const C3: Color = Color::new(3, 0, 0, 255);
+
+    #[test]
+    fn cycling() {
+        let mut animator =
+            PaletteAnimator::new(vec![C0, C1, C2, C3], vec![PaletteCycle::new(1, 3, 1.0)]);
+        assert!(!animator.update_secs(0.5));
+        assert_eq!(animator.palette(), &[C0, C1, C2, C3]);
+        assert!(animator.update_secs(0.5));
+        assert_eq!(animator.palette(), &[C0, C3, C1, C2]);
+        assert!(animator.update_secs(1.0));
+        assert_eq!(animator.palette(), &[C0, C2, C3, C1]);
+        assert!(animator.update_secs(1.0));
+        assert_eq!(animator.palette(), &[C0, C1, C2, C3]);
+        animator.update_secs(1.0);
+        animator.reset();
+        assert_eq!(animator.palette(), &[C0, C1, C2, C3]);
+    }
+
+    #[test]
+    fn swapping() {
+        let palette = vec![C0, C1];
+        let tileset = Tileset::new(
+            vec![Rc::new(
+                IndexedImage::new(1, 2, palette.clone(), vec![0, 1]).unwrap(),
+            )],
+            vec![String::from("tile")],
+            (1, 2),
+        );
+        let mut tilemap = Tilemap::new(
+            vec![0],
+            vec![0],
+            MapSize::new(1, 1),
+            vec![String::from("tile")],
+            tileset,
+            (1, 2),
+            MapPosition::new(0, 0),
+            vec![],
+        )
+        .unwrap();
+        tilemap.set_palette(&[C2, C3]).unwrap();
+        assert_eq!(
+            tilemap
+                .image_for_tile(MapPosition::new(0, 0))
+                .unwrap()
+                .get_palette(),
+            &[C2, C3]
+        );
+        assert!(matches!(
+            tilemap.set_palette(&[C0]),
+            Err(GameUtilError::InvalidPalette(_))
+        ));
+    }
+}
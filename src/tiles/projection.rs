@@ -0,0 +1,354 @@
+use crate::tiles::units::{Direction, TilePos};
+use serde::{Deserialize, Serialize};
+
+const SQRT_3: f64 = 1.732_050_807_568_877_2;
+
+/// Neighbours for hexagonal grids in axial coordinates
+const AXIAL_NEIGHBOURS: [(i32, i32); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+
+/// How tiles are laid out on screen
+///
+/// The tile size (from the [Tileset](crate::tiles::tileset::Tileset)) is the size of
+/// the bounding box of a single tile image, for isometric this is the width and height of
+/// the diamond and for hexagonal it's the width and height of the hexagon
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+pub enum Projection {
+    /// Square tiles placed edge to edge
+    #[default]
+    Orthogonal,
+    /// Diamond shaped tiles
+    Isometric(IsoLayout),
+    /// Hexagon shaped tiles, stored using offset coordinates
+    ///
+    /// See [AxialPosition] for converting to axial coordinates
+    Hexagonal(HexOrientation, HexOffset),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum IsoLayout {
+    /// Map is drawn as a large diamond, x increases down-right and y increases down-left
+    Diamond,
+    /// Map is drawn as a rectangle, with odd rows shifted right by half a tile
+    Staggered,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum HexOrientation {
+    /// Hexagons have a point at the top, rows are offset
+    Pointy,
+    /// Hexagons have a flat top, columns are offset
+    Flat,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum HexOffset {
+    /// Odd rows (or columns for [HexOrientation::Flat]) are shifted right (or down)
+    Odd,
+    /// Even rows (or columns for [HexOrientation::Flat]) are shifted right (or down)
+    Even,
+}
+
+/// Axial coordinates for hexagonal grids
+///
+/// See <https://www.redblobgames.com/grids/hexagons/#coordinates-axial>
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AxialPosition {
+    pub q: i32,
+    pub r: i32,
+}
+
+impl AxialPosition {
+    #[inline]
+    pub const fn new(q: i32, r: i32) -> Self {
+        Self { q, r }
+    }
+
+    /// Convert from offset coordinates (as used by [Tilemap](crate::tiles::tilemap::Tilemap))
+    pub fn from_offset<P: Into<TilePos>>(
+        tile: P,
+        orientation: HexOrientation,
+        offset: HexOffset,
+    ) -> Self {
+        let TilePos { x, y } = tile.into();
+        match (orientation, offset) {
+            (HexOrientation::Pointy, HexOffset::Odd) => Self::new(x - (y - (y & 1)) / 2, y),
+            (HexOrientation::Pointy, HexOffset::Even) => Self::new(x - (y + (y & 1)) / 2, y),
+            (HexOrientation::Flat, HexOffset::Odd) => Self::new(x, y - (x - (x & 1)) / 2),
+            (HexOrientation::Flat, HexOffset::Even) => Self::new(x, y - (x + (x & 1)) / 2),
+        }
+    }
+
+    /// Convert to offset coordinates (as used by [Tilemap](crate::tiles::tilemap::Tilemap))
+    pub fn to_offset(&self, orientation: HexOrientation, offset: HexOffset) -> TilePos {
+        let (q, r) = (self.q, self.r);
+        match (orientation, offset) {
+            (HexOrientation::Pointy, HexOffset::Odd) => TilePos::new(q + (r - (r & 1)) / 2, r),
+            (HexOrientation::Pointy, HexOffset::Even) => TilePos::new(q + (r + (r & 1)) / 2, r),
+            (HexOrientation::Flat, HexOffset::Odd) => TilePos::new(q, r + (q - (q & 1)) / 2),
+            (HexOrientation::Flat, HexOffset::Even) => TilePos::new(q, r + (q + (q & 1)) / 2),
+        }
+    }
+
+    /// Number of steps between two hexes
+    pub fn distance(&self, other: &AxialPosition) -> u32 {
+        let dq = self.q - other.q;
+        let dr = self.r - other.r;
+        (dq.unsigned_abs() + dr.unsigned_abs() + (dq + dr).unsigned_abs()) / 2
+    }
+}
+
+impl Projection {
+    /// Top left px of the bounding box of `tile`, relative to tile 0,0
+    pub fn origin_px<P: Into<TilePos>>(&self, tile: P, tile_size: (u32, u32)) -> (isize, isize) {
+        let tile = tile.into();
+        let (x, y) = (tile.x as isize, tile.y as isize);
+        let (w, h) = (tile_size.0 as isize, tile_size.1 as isize);
+        match self {
+            Projection::Orthogonal => (x * w, y * h),
+            Projection::Isometric(IsoLayout::Diamond) => {
+                (((x - y) * w).div_euclid(2), ((x + y) * h).div_euclid(2))
+            }
+            Projection::Isometric(IsoLayout::Staggered) => {
+                (x * w + (y & 1) * w / 2, (y * h).div_euclid(2))
+            }
+            Projection::Hexagonal(HexOrientation::Pointy, offset) => {
+                let shift = if is_shifted(y, *offset) { w / 2 } else { 0 };
+                (x * w + shift, (y * h * 3).div_euclid(4))
+            }
+            Projection::Hexagonal(HexOrientation::Flat, offset) => {
+                let shift = if is_shifted(x, *offset) { h / 2 } else { 0 };
+                ((x * w * 3).div_euclid(4), y * h + shift)
+            }
+        }
+    }
+
+    /// Tile containing `px`, where `px` is relative to the origin of tile 0,0
+    ///
+    /// Result may be outside the map
+    pub fn tile_at_px(&self, px: (isize, isize), tile_size: (u32, u32)) -> TilePos {
+        let (px_x, px_y) = (px.0 as f64, px.1 as f64);
+        let (w, h) = (tile_size.0 as f64, tile_size.1 as f64);
+        let guess = match self {
+            Projection::Orthogonal => {
+                return TilePos::new(
+                    px.0.div_euclid(tile_size.0 as isize) as i32,
+                    px.1.div_euclid(tile_size.1 as isize) as i32,
+                )
+            }
+            Projection::Isometric(IsoLayout::Diamond) => {
+                let a = (px_x - w / 2.0) / (w / 2.0);
+                let b = px_y / (h / 2.0);
+                (((a + b) / 2.0).floor(), ((b - a) / 2.0).floor())
+            }
+            Projection::Isometric(IsoLayout::Staggered) => {
+                let row = (px_y / (h / 2.0)).floor();
+                let shift = if (row as i64) & 1 == 1 { w / 2.0 } else { 0.0 };
+                (((px_x - shift) / w).floor(), row)
+            }
+            Projection::Hexagonal(HexOrientation::Pointy, offset) => {
+                let row = (px_y / (h * 0.75)).floor();
+                let shift = if is_shifted(row as isize, *offset) {
+                    w / 2.0
+                } else {
+                    0.0
+                };
+                (((px_x - shift) / w).floor(), row)
+            }
+            Projection::Hexagonal(HexOrientation::Flat, offset) => {
+                let col = (px_x / (w * 0.75)).floor();
+                let shift = if is_shifted(col as isize, *offset) {
+                    h / 2.0
+                } else {
+                    0.0
+                };
+                (col, ((px_y - shift) / h).floor())
+            }
+        };
+        let guess = TilePos::new(guess.0 as i32, guess.1 as i32);
+
+        //the guess is always within one tile, so pick the closest tile center
+        let mut best = guess;
+        let mut best_dist = f64::MAX;
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let tile = guess + TilePos::new(x, y);
+                let origin = self.origin_px(tile, tile_size);
+                let dx = px_x - (origin.0 as f64 + w / 2.0);
+                let dy = px_y - (origin.1 as f64 + h / 2.0);
+                let dist = self.shape_distance(dx, dy, w, h);
+                if dist < best_dist {
+                    best_dist = dist;
+                    best = tile;
+                }
+            }
+        }
+        best
+    }
+
+    /// Distance from the center of a tile, normalised so that tiles are the same size
+    fn shape_distance(&self, dx: f64, dy: f64, w: f64, h: f64) -> f64 {
+        match self {
+            Projection::Orthogonal => (dx.abs() / w).max(dy.abs() / h),
+            Projection::Isometric(_) => dx.abs() / w + dy.abs() / h,
+            Projection::Hexagonal(HexOrientation::Pointy, _) => {
+                (dx * SQRT_3 / w).hypot(dy * 2.0 / h)
+            }
+            Projection::Hexagonal(HexOrientation::Flat, _) => (dx * 2.0 / w).hypot(dy * SQRT_3 / h),
+        }
+    }
+
+    /// All tiles sharing an edge with `tile`
+    ///
+    /// Result may include tiles outside the map
+    pub fn neighbours<P: Into<TilePos>>(&self, tile: P) -> Vec<TilePos> {
+        let tile = tile.into();
+        match self {
+            Projection::Orthogonal | Projection::Isometric(IsoLayout::Diamond) => {
+                tile.neighbours_4().to_vec()
+            }
+            Projection::Isometric(IsoLayout::Staggered) => {
+                let diamond = staggered_to_diamond(tile);
+                Direction::CARDINAL
+                    .iter()
+                    .map(|dir| diamond_to_staggered(diamond.step(*dir)))
+                    .collect()
+            }
+            Projection::Hexagonal(orientation, offset) => {
+                let axial = AxialPosition::from_offset(tile, *orientation, *offset);
+                AXIAL_NEIGHBOURS
+                    .iter()
+                    .map(|(q, r)| {
+                        AxialPosition::new(axial.q + q, axial.r + r)
+                            .to_offset(*orientation, *offset)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Minimum number of steps between `from` and `to` when moving between neighbours
+    pub fn distance<P: Into<TilePos>>(&self, from: P, to: P) -> u32 {
+        let (from, to) = (from.into(), to.into());
+        match self {
+            Projection::Orthogonal | Projection::Isometric(IsoLayout::Diamond) => {
+                from.manhattan(to)
+            }
+            Projection::Isometric(IsoLayout::Staggered) => {
+                staggered_to_diamond(from).manhattan(staggered_to_diamond(to))
+            }
+            Projection::Hexagonal(orientation, offset) => {
+                AxialPosition::from_offset(from, *orientation, *offset)
+                    .distance(&AxialPosition::from_offset(to, *orientation, *offset))
+            }
+        }
+    }
+}
+
+#[inline]
+fn is_shifted(value: isize, offset: HexOffset) -> bool {
+    let odd = value & 1 == 1;
+    match offset {
+        HexOffset::Odd => odd,
+        HexOffset::Even => !odd,
+    }
+}
+
+fn staggered_to_diamond(tile: TilePos) -> TilePos {
+    let x = tile.x + (tile.y + 1).div_euclid(2);
+    TilePos::new(x, tile.y - x)
+}
+
+fn diamond_to_staggered(tile: TilePos) -> TilePos {
+    let y = tile.x + tile.y;
+    TilePos::new(tile.x - (y + 1).div_euclid(2), y)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ALL: [Projection; 7] = [
+        Projection::Orthogonal,
+        Projection::Isometric(IsoLayout::Diamond),
+        Projection::Isometric(IsoLayout::Staggered),
+        Projection::Hexagonal(HexOrientation::Pointy, HexOffset::Odd),
+        Projection::Hexagonal(HexOrientation::Pointy, HexOffset::Even),
+        Projection::Hexagonal(HexOrientation::Flat, HexOffset::Odd),
+        Projection::Hexagonal(HexOrientation::Flat, HexOffset::Even),
+    ];
+
+    #[test]
+    fn picking_tile_centers() {
+        for projection in ALL {
+            for y in -3..6 {
+                for x in -3..6 {
+                    let origin = projection.origin_px((x, y), (32, 16));
+                    let center = (origin.0 + 16, origin.1 + 8);
+                    assert_eq!(
+                        projection.tile_at_px(center, (32, 16)),
+                        TilePos::new(x, y),
+                        "{projection:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn iso_px() {
+        let diamond = Projection::Isometric(IsoLayout::Diamond);
+        assert_eq!(diamond.origin_px((0, 0), (32, 16)), (0, 0));
+        assert_eq!(diamond.origin_px((1, 0), (32, 16)), (16, 8));
+        assert_eq!(diamond.origin_px((0, 1), (32, 16)), (-16, 8));
+        assert_eq!(diamond.tile_at_px((16, 1), (32, 16)), TilePos::new(0, 0));
+        assert_eq!(diamond.tile_at_px((1, 8), (32, 16)), TilePos::new(0, 0));
+        assert_eq!(diamond.tile_at_px((-1, 8), (32, 16)), TilePos::new(-1, 1));
+
+        let staggered = Projection::Isometric(IsoLayout::Staggered);
+        assert_eq!(staggered.origin_px((0, 1), (32, 16)), (16, 8));
+        assert_eq!(staggered.tile_at_px((1, 1), (32, 16)), TilePos::new(-1, -1));
+    }
+
+    #[test]
+    fn neighbours_are_adjacent() {
+        for projection in ALL {
+            for tile in [(0, 0), (3, 4), (4, 3), (-1, -2)].map(TilePos::from) {
+                let neighbours = projection.neighbours(tile);
+                let expected = match projection {
+                    Projection::Hexagonal(_, _) => 6,
+                    _ => 4,
+                };
+                assert_eq!(neighbours.len(), expected, "{projection:?}");
+                for neighbour in neighbours {
+                    assert_eq!(projection.distance(tile, neighbour), 1, "{projection:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn distance() {
+        let hex = Projection::Hexagonal(HexOrientation::Pointy, HexOffset::Odd);
+        assert_eq!(hex.distance((0, 0), (3, 0)), 3);
+        assert_eq!(hex.distance((0, 0), (0, 2)), 2);
+        assert_eq!(hex.distance((0, 0), (2, 4)), 4);
+        let staggered = Projection::Isometric(IsoLayout::Staggered);
+        assert_eq!(staggered.distance((0, 0), (0, 2)), 2);
+        assert_eq!(staggered.distance((0, 0), (1, 0)), 2);
+        assert_eq!(Projection::Orthogonal.distance((0, 0), (3, 4)), 7);
+    }
+
+    #[test]
+    fn axial_round_trip() {
+        for orientation in [HexOrientation::Pointy, HexOrientation::Flat] {
+            for offset in [HexOffset::Odd, HexOffset::Even] {
+                for y in -4..4 {
+                    for x in -4..4 {
+                        let axial = AxialPosition::from_offset((x, y), orientation, offset);
+                        assert_eq!(axial.to_offset(orientation, offset), TilePos::new(x, y));
+                    }
+                }
+            }
+        }
+    }
+}
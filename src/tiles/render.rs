@@ -0,0 +1,267 @@
+use std::collections::{HashMap, HashSet};
+
+use ici_files::prelude::*;
+
+use crate::prelude::*;
+
+/// Options for [Tilemap::render_to_image]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    /// Area of the map to render, defaults to the whole map
+    ///
+    /// Tiles outside the map (after wrapping) are filled with `background`
+    pub region: Option<MapRect>,
+    /// Size to draw each tile at, scaled using nearest neighbour, defaults to the tile size
+    pub tile_size: Option<(u8, u8)>,
+    /// Colors blended over tiles that have all bits of the flag set, applied in order
+    pub overlays: Vec<(u32, Color)>,
+    /// Color used for tiles outside the map
+    pub background: Color,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            region: None,
+            tile_size: None,
+            overlays: vec![],
+            background: TRANSPARENT,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_region(mut self, region: MapRect) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn with_tile_size(mut self, tile_size: (u8, u8)) -> Self {
+        self.tile_size = Some(tile_size);
+        self
+    }
+
+    pub fn with_overlay(mut self, flag: u32, color: Color) -> Self {
+        self.overlays.push((flag, color));
+        self
+    }
+
+    pub fn with_background(mut self, color: Color) -> Self {
+        self.background = color;
+        self
+    }
+}
+
+/// Builds a palette as colors are used
+#[derive(Debug, Default)]
+struct PaletteBuilder {
+    colors: Vec<Color>,
+    lookup: HashMap<Color, u8>,
+    /// Colors that didn't fit
+    overflow: HashSet<Color>,
+}
+
+impl PaletteBuilder {
+    fn index(&mut self, color: Color) -> u8 {
+        if let Some(idx) = self.lookup.get(&color) {
+            return *idx;
+        }
+        if self.colors.len() > u8::MAX as usize {
+            self.overflow.insert(color);
+            return 0;
+        }
+        let idx = self.colors.len() as u8;
+        self.colors.push(color);
+        self.lookup.insert(color, idx);
+        idx
+    }
+}
+
+impl Tilemap<IndexedImage> {
+    /// Draws the map (or [RenderOptions::region] of it) into a single image
+    ///
+    /// Tiles are always laid out orthogonally, regardless of [Tilemap::projection], and the
+    /// map offset is ignored. The palette of the returned image only contains colors that are used,
+    /// in the order they first appear (left to right, then top to bottom)
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::ImageTooLarge] if the image would be wider or taller than 255px
+    /// [GameUtilError::TooManyColors] if more than 256 colors are needed
+    pub fn render_to_image(&self, options: &RenderOptions) -> Result<IndexedImage, GameUtilError> {
+        let region = options
+            .region
+            .unwrap_or_else(|| MapRect::from_size(self.size()));
+        let (tile_w, tile_h) = options.tile_size.unwrap_or_else(|| {
+            let (w, h) = self.tile_size();
+            (w.min(u8::MAX as u32) as u8, h.min(u8::MAX as u32) as u8)
+        });
+        let (tile_w, tile_h) = (tile_w as usize, tile_h as usize);
+        let width = region.w as usize * tile_w;
+        let height = region.h as usize * tile_h;
+        if width > u8::MAX as usize || height > u8::MAX as usize {
+            return Err(GameUtilError::ImageTooLarge(width, height));
+        }
+
+        let mut colors = vec![options.background; width * height];
+        for (i, pos) in region.iter().enumerate() {
+            let px_x = (i % region.w as usize) * tile_w;
+            let px_y = (i / region.w as usize) * tile_h;
+            let Some(tile) = self.wrap_tile_pos(pos) else {
+                continue;
+            };
+            let Some(image) = self.image_for_tile(tile) else {
+                continue;
+            };
+            let flags = self.flags_for_tile(tile);
+            let overlays: Vec<Color> = options
+                .overlays
+                .iter()
+                .filter(|(flag, _)| flags & flag == *flag)
+                .map(|(_, color)| *color)
+                .collect();
+            let palette = image.get_palette();
+            let pixels = image.get_pixels();
+            let src_w = image.width() as usize;
+            let src_h = image.height() as usize;
+            for y in 0..tile_h {
+                let src_y = y * src_h / tile_h;
+                for x in 0..tile_w {
+                    let src_x = x * src_w / tile_w;
+                    let mut color = palette[pixels[src_y * src_w + src_x] as usize];
+                    for overlay in &overlays {
+                        color = color.blend(*overlay);
+                    }
+                    colors[(px_y + y) * width + px_x + x] = color;
+                }
+            }
+        }
+
+        let mut palette = PaletteBuilder::default();
+        let pixels: Vec<u8> = colors.into_iter().map(|c| palette.index(c)).collect();
+        if !palette.overflow.is_empty() {
+            return Err(GameUtilError::TooManyColors(
+                palette.colors.len() + palette.overflow.len(),
+            ));
+        }
+        if palette.colors.is_empty() {
+            palette.colors.push(options.background);
+        }
+
+        IndexedImage::new(width as u8, height as u8, palette.colors, pixels)
+            .map_err(|e| GameUtilError::InvalidImage(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::rc::Rc;
+
+    const RED: Color = Color::new(255, 0, 0, 255);
+    const BLUE: Color = Color::new(0, 0, 255, 255);
+    const WHITE: Color = Color::new(255, 255, 255, 255);
+
+    fn map() -> Tilemap<IndexedImage> {
+        let palette = vec![RED, BLUE];
+        let tileset = Tileset::new(
+            vec![
+                Rc::new(IndexedImage::new(2, 2, palette.clone(), vec![0, 0, 0, 0]).unwrap()),
+                Rc::new(IndexedImage::new(2, 2, palette.clone(), vec![1, 0, 0, 1]).unwrap()),
+            ],
+            vec![String::from("red"), String::from("check")],
+            (2, 2),
+        );
+        Tilemap::new(
+            vec![0, 1, 1, 0],
+            vec![0, 0, 1, 0],
+            MapSize::new(2, 2),
+            vec![String::from("red"), String::from("check")],
+            tileset,
+            (4, 4),
+            MapPosition::new(0, 0),
+            vec![],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn whole_map() {
+        let image = map().render_to_image(&RenderOptions::default()).unwrap();
+        assert_eq!(image.size(), (4, 4));
+        assert_eq!(image.get_palette(), &[RED, BLUE]);
+        #[rustfmt::skip]
+        assert_eq!(image.get_pixels(), &[
+            0, 0, 1, 0,
+            0, 0, 0, 1,
+            1, 0, 0, 0,
+            0, 1, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn scaled_region_with_overlay() {
+        let options = RenderOptions::new()
+            .with_region(MapRect::new(TilePos::new(0, 1), 2, 2))
+            .with_tile_size((1, 1))
+            .with_overlay(1, WHITE);
+        let image = map().render_to_image(&options).unwrap();
+        assert_eq!(image.size(), (2, 2));
+        assert_eq!(image.get_palette(), &[WHITE, RED, TRANSPARENT]);
+        assert_eq!(image.get_pixels(), &[0, 1, 2, 2]);
+
+        let image = map()
+            .render_to_image(&RenderOptions::new().with_tile_size((4, 4)))
+            .unwrap();
+        assert_eq!(image.size(), (8, 8));
+        assert_eq!(&image.get_pixels()[8..16], &[0, 0, 0, 0, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn wrapping() {
+        let mut map = map();
+        map.set_wrap_mode(WrapMode::Horizontal);
+        let options = RenderOptions::new()
+            .with_region(MapRect::new(TilePos::new(-1, 0), 2, 1))
+            .with_tile_size((1, 1));
+        let image = map.render_to_image(&options).unwrap();
+        assert_eq!(image.get_palette(), &[BLUE, RED]);
+        assert_eq!(image.get_pixels(), &[0, 1]);
+    }
+
+    #[test]
+    fn too_large() {
+        let result = map().render_to_image(&RenderOptions::new().with_tile_size((128, 1)));
+        assert!(matches!(result, Err(GameUtilError::ImageTooLarge(256, 2))));
+    }
+
+    #[test]
+    fn too_many_colors() {
+        let image = |green: u8| {
+            let palette = (0..=255)
+                .map(|red| Color::new(red, green, 0, 255))
+                .collect();
+            Rc::new(IndexedImage::new(16, 16, palette, (0..=255).collect()).unwrap())
+        };
+        let names = vec![String::from("a"), String::from("b")];
+        let tileset = Tileset::new(vec![image(0), image(1)], names.clone(), (16, 16));
+        //the second tile is drawn twice but its colors are only counted once
+        let map = Tilemap::new(
+            vec![0, 1, 1],
+            vec![0; 3],
+            MapSize::new(3, 1),
+            names,
+            tileset,
+            (48, 16),
+            MapPosition::new(0, 0),
+            vec![],
+        )
+        .unwrap();
+        let result = map.render_to_image(&RenderOptions::default());
+        assert!(matches!(result, Err(GameUtilError::TooManyColors(512))));
+    }
+}
@@ -1,9 +1,54 @@
 use crate::prelude::*;
 use log::error;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::rc::Rc;
 
+/// Which edges of the map connect to the opposite edge
+///
+/// Note for staggered isometric and hexagonal maps the size of a wrapping axis should be even
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+pub enum WrapMode {
+    #[default]
+    None,
+    /// Left and right edges connect
+    Horizontal,
+    /// Top and bottom edges connect
+    Vertical,
+    Both,
+}
+
+/// Images that change over time, see [Tilemap::update]
+pub trait AnimatedImage {
+    /// Advance the animation by `delta` seconds
+    fn update(&mut self, delta: f64);
+}
+
+/// Order tiles are drawn in for orthogonal maps
+///
+/// Other projections are always drawn back to front
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum DrawOrder {
+    /// Top to bottom, then left to right
+    #[default]
+    ColumnMajor,
+    /// Left to right, then top to bottom
+    RowMajor,
+}
+
+impl WrapMode {
+    #[inline]
+    pub fn wraps_x(&self) -> bool {
+        matches!(self, WrapMode::Horizontal | WrapMode::Both)
+    }
+
+    #[inline]
+    pub fn wraps_y(&self) -> bool {
+        matches!(self, WrapMode::Vertical | WrapMode::Both)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Tilemap<Image: Debug + Clone> {
     ///index to `image`
@@ -13,13 +58,18 @@ pub struct Tilemap<Image: Debug + Clone> {
     size: MapSize,
     ///number of tiles visible on screen
     visible_size: MapSize,
-    ///top left offset for rendering (in tiles)
-    offset: MapPosition,
+    ///top left offset for rendering (in tiles), negative if the camera is past the left or top edge
+    offset: TilePos,
     images: Vec<Rc<Image>>,
     tile_size: (u32, u32),
+    ///size of the area the map is drawn in (in px)
+    render_size: (u32, u32),
     subtile_offset: (i16, i16),
     default_start: MapPosition,
     exits: Vec<MapExit>,
+    projection: Projection,
+    wrap: WrapMode,
+    draw_order: DrawOrder,
 }
 
 impl<Image: Debug + Clone> Tilemap<Image> {
@@ -51,67 +101,197 @@ impl<Image: Debug + Clone> Tilemap<Image> {
             ));
         }
 
-        let mut visible_size = MapSize::new(
-            render_size.0 / tileset.tilesize().0,
-            render_size.1 / tileset.tilesize().1,
-        );
-        visible_size.w = visible_size.w.min(size.w);
-        visible_size.h = visible_size.h.min(size.h);
+        let visible_size = calc_visible_size(render_size, tileset.tilesize(), size, WrapMode::None);
 
         Ok(Self {
             tiles,
             flags,
             size,
             tile_size: tileset.tilesize(),
+            render_size,
             visible_size,
-            offset: MapPosition::new(0, 0),
+            offset: TilePos::ZERO,
             images,
             subtile_offset: (0, 0),
             default_start,
             exits,
+            projection: Projection::Orthogonal,
+            wrap: WrapMode::None,
+            draw_order: DrawOrder::ColumnMajor,
         })
     }
 }
 
+/// Number of tiles visible on screen, only axes that wrap can show more tiles than the map has
+fn calc_visible_size(
+    render_size: (u32, u32),
+    tile_size: (u32, u32),
+    size: MapSize,
+    wrap: WrapMode,
+) -> MapSize {
+    let mut visible_size = MapSize::new(render_size.0 / tile_size.0, render_size.1 / tile_size.1);
+    if !wrap.wraps_x() {
+        visible_size.w = visible_size.w.min(size.w);
+    }
+    if !wrap.wraps_y() {
+        visible_size.h = visible_size.h.min(size.h);
+    }
+    visible_size
+}
+
+/// Position with negative coords moved to 0
+fn clamp_to_origin(pos: TilePos) -> MapPosition {
+    MapPosition::new(pos.x.max(0) as u32, pos.y.max(0) as u32)
+}
+
 impl<Image: Debug + Clone> Tilemap<Image> {
     /// Pixel coord for tile
     /// Result may be offscreen, before or after
     pub fn px_for_tile<P: Into<MapPosition>>(&self, tile: P) -> (isize, isize) {
-        let tile = tile.into();
+        let (x, y) = self.orig_px_for_tile(tile);
         (
-            (self.tile_size.0 * tile.x) as isize - (self.tile_size.0 * self.offset.x) as isize
-                + self.subtile_offset.0 as isize,
-            (self.tile_size.1 * tile.y) as isize - (self.tile_size.1 * self.offset.y) as isize
-                + self.subtile_offset.1 as isize,
+            x + self.subtile_offset.0 as isize,
+            y + self.subtile_offset.1 as isize,
         )
     }
 
     /// Pixel coord for tile, ignoring subtile offset
     /// Result may be offscreen, before or after
     pub fn orig_px_for_tile<P: Into<MapPosition>>(&self, tile: P) -> (isize, isize) {
-        let tile = tile.into();
+        self.orig_px_for_tile_pos(TilePos::from(tile.into()))
+    }
+
+    /// Pixel coord for tile, `tile` may be outside the map
+    /// Result may be offscreen, before or after
+    pub fn px_for_tile_pos<P: Into<TilePos>>(&self, tile: P) -> (isize, isize) {
+        let (x, y) = self.orig_px_for_tile_pos(tile);
         (
-            (self.tile_size.0 * tile.x) as isize - (self.tile_size.0 * self.offset.x) as isize,
-            (self.tile_size.1 * tile.y) as isize - (self.tile_size.1 * self.offset.y) as isize,
+            x + self.subtile_offset.0 as isize,
+            y + self.subtile_offset.1 as isize,
         )
     }
 
+    fn orig_px_for_tile_pos<P: Into<TilePos>>(&self, tile: P) -> (isize, isize) {
+        let tile = self.projection.origin_px(tile, self.tile_size);
+        let offset = self.offset_px();
+        (tile.0 - offset.0, tile.1 - offset.1)
+    }
+
+    /// Origin px of the top left tile being rendered
+    fn offset_px(&self) -> (isize, isize) {
+        self.projection.origin_px(self.offset, self.tile_size)
+    }
+
+    /// Tile at pixel coord `px`, this is the inverse of [Tilemap::px_for_tile]
+    ///
+    /// Returns None if `px` is not over the map
+    pub fn tile_for_px(&self, px: (isize, isize)) -> Option<MapPosition> {
+        self.wrap_tile_pos(self.tile_pos_for_px(px))
+    }
+
+    /// Tile at pixel coord `px`, this is the inverse of [Tilemap::px_for_tile_pos]
+    ///
+    /// Result may be outside the map
+    pub fn tile_pos_for_px(&self, px: (isize, isize)) -> TilePos {
+        let offset = self.offset_px();
+        self.projection.tile_at_px(
+            (
+                px.0 - self.subtile_offset.0 as isize + offset.0,
+                px.1 - self.subtile_offset.1 as isize + offset.1,
+            ),
+            self.tile_size,
+        )
+    }
+
+    /// Returns the position of `tile` inside the map
+    ///
+    /// If the map wraps, positions off one edge will be moved to the opposite edge,
+    /// otherwise returns None if `tile` is outside the map
+    pub fn wrap_tile_pos<P: Into<TilePos>>(&self, tile: P) -> Option<MapPosition> {
+        let mut tile = tile.into();
+        if self.wrap.wraps_x() {
+            tile.x = tile.x.rem_euclid(self.size.w as i32);
+        }
+        if self.wrap.wraps_y() {
+            tile.y = tile.y.rem_euclid(self.size.h as i32);
+        }
+        MapPosition::try_from(tile)
+            .ok()
+            .filter(|tile| tile.x < self.size.w && tile.y < self.size.h)
+    }
+
+    /// Returns the position of `tile` inside the map
+    ///
+    /// See [Tilemap::wrap_tile_pos]
+    pub fn wrap_position<P: Into<MapPosition>>(&self, tile: P) -> Option<MapPosition> {
+        self.wrap_tile_pos(TilePos::from(tile.into()))
+    }
+
+    /// Area of the map visible on screen (in tiles), ignoring subtile offset
+    ///
+    /// For non orthogonal maps this is only an approximation
+    pub fn visible_rect(&self) -> MapRect {
+        MapRect::new(self.offset, self.visible_size.w, self.visible_size.h)
+    }
+
+    /// All tiles inside the map that share an edge with `tile`
+    ///
+    /// Returns 4 tiles for orthogonal and isometric maps and 6 for hexagonal maps (fewer at the edges)
+    pub fn neighbours<P: Into<MapPosition>>(&self, tile: P) -> Vec<MapPosition> {
+        let tile = tile.into();
+        self.projection
+            .neighbours(TilePos::from(tile))
+            .into_iter()
+            .filter_map(|tile| self.wrap_tile_pos(tile))
+            .collect()
+    }
+
+    /// Minimum number of steps between `from` and `to` when moving between neighbours
+    ///
+    /// If the map wraps this includes moving across the edges
+    pub fn distance<P: Into<MapPosition>>(&self, from: P, to: P) -> u32 {
+        let from = TilePos::from(from.into());
+        let to = TilePos::from(to.into());
+        let (w, h) = (self.size.w as i32, self.size.h as i32);
+        let x_copies: &[i32] = if self.wrap.wraps_x() {
+            &[-w, 0, w]
+        } else {
+            &[0]
+        };
+        let y_copies: &[i32] = if self.wrap.wraps_y() {
+            &[-h, 0, h]
+        } else {
+            &[0]
+        };
+        let mut distance = u32::MAX;
+        for x in x_copies {
+            for y in y_copies {
+                let to = to + TilePos::new(*x, *y);
+                distance = distance.min(self.projection.distance(from, to));
+            }
+        }
+        distance
+    }
+
     /// Returns the pos of the first tile with at least one pixel visible
     ///
-    /// Will match [Tilemap::first_visible_tile] unless a subtile offset is set
+    /// Will match [Tilemap::first_fully_visible_tile] unless a subtile offset is set
+    ///
+    /// Clamped to the top left of the map, use [Tilemap::first_visible_tile_pos] if the camera can be past the edge
     pub fn first_visible_tile(&self) -> MapPosition {
+        clamp_to_origin(self.first_visible_tile_pos())
+    }
+
+    /// Returns the pos of the first tile with at least one pixel visible, may be outside the map
+    pub fn first_visible_tile_pos(&self) -> TilePos {
         if self.subtile_offset == (0, 0) {
             self.offset
         } else {
-            let (x_offset, y_offset) = self.tiles_visible_from_subtile_offset();
-            let mut offset = self.offset;
-            offset.x = offset.x.saturating_add_signed(x_offset);
-            offset.y = offset.y.saturating_add_signed(y_offset);
-            offset
+            self.offset + self.tiles_visible_from_subtile_offset()
         }
     }
 
-    fn tiles_visible_from_subtile_offset(&self) -> (i32, i32) {
+    fn tiles_visible_from_subtile_offset(&self) -> TilePos {
         let x_offset = self.subtile_offset.0 as f64 / self.tile_size.0 as f64;
         let x_offset = if x_offset.is_sign_positive() {
             x_offset.ceil()
@@ -124,57 +304,57 @@ impl<Image: Debug + Clone> Tilemap<Image> {
         } else {
             y_offset.floor()
         } as i32;
-        (x_offset, y_offset)
+        TilePos::new(x_offset, y_offset)
     }
 
     /// Returns the pos of the first fully visible tile
     ///
+    /// Clamped to the top left of the map, use [Tilemap::visible_rect] if the camera can be past the edge
+    ///
     /// See [Tilemap::first_visible_tile]
     pub fn first_fully_visible_tile(&self) -> MapPosition {
-        self.offset
+        clamp_to_origin(self.offset)
     }
 
     /// Returns the pos of the last tile with at least one pixel visible
     ///
-    /// Will match [Tilemap::last_visible_tile] unless a subtile offset is set
+    /// Will match [Tilemap::last_fully_visible_tile] unless a subtile offset is set
+    ///
+    /// Clamped to the top left of the map, use [Tilemap::last_visible_tile_pos] if the camera can be past the edge
     pub fn last_visible_tile(&self) -> MapPosition {
-        let mut offset = self.offset;
-        offset.x += self.visible_size.w;
-        offset.y += self.visible_size.h;
+        clamp_to_origin(self.last_visible_tile_pos())
+    }
+
+    /// Returns the pos of the last tile with at least one pixel visible, may be outside the map
+    pub fn last_visible_tile_pos(&self) -> TilePos {
+        let end = self.visible_rect().end();
         if self.subtile_offset == (0, 0) {
-            offset
+            end
         } else {
-            let (x_offset, y_offset) = self.tiles_visible_from_subtile_offset();
-            offset.x = offset.x.saturating_add_signed(x_offset);
-            offset.y = offset.y.saturating_add_signed(y_offset);
-            offset
+            end + self.tiles_visible_from_subtile_offset()
         }
     }
 
     /// Returns the pos of the last fully visible tile
     ///
+    /// Clamped to the top left of the map, use [Tilemap::visible_rect] if the camera can be past the edge
+    ///
     /// See [Tilemap::last_visible_tile]
     pub fn last_fully_visible_tile(&self) -> MapPosition {
-        let mut offset = self.offset;
-        offset.x += self.visible_size.w;
-        offset.y += self.visible_size.h;
-        offset
+        clamp_to_origin(self.visible_rect().end())
     }
 
     /// Returns true if `tile` is inside the map
+    ///
+    /// Always true for wrapping axes
     pub fn is_inside<P: Into<MapPosition>>(&self, tile: P) -> bool {
         let tile = tile.into();
-        tile.x < self.size.w && tile.y < self.size.h
+        (self.wrap.wraps_x() || tile.x < self.size.w)
+            && (self.wrap.wraps_y() || tile.y < self.size.h)
     }
 
     fn tile_idx<P: Into<MapPosition>>(&self, tile: P) -> Option<usize> {
-        let tile = tile.into();
-        let i = tile.to_idx(self.size);
-        if i < self.tiles.len() {
-            Some(i)
-        } else {
-            None
-        }
+        self.wrap_position(tile).map(|tile| tile.to_idx(self.size))
     }
 
     fn tile_pos(&self, tile: usize) -> Option<MapPosition> {
@@ -185,21 +365,128 @@ impl<Image: Debug + Clone> Tilemap<Image> {
         }
     }
 
-    /// Loops through all visible tiles
+    /// Loops through all visible tiles (including partially visible ones)
     /// calling `render` with the image and px coord
+    ///
+    /// Orthogonal maps are drawn using [DrawOrder], other maps are drawn back to front (top to bottom, then left to right)
     pub fn draw<F: FnMut(&Image, (isize, isize))>(&self, mut render: F) {
-        for x in 0..self.visible_size.w {
-            for y in 0..self.visible_size.h {
-                let x = x.saturating_add(self.offset.x);
-                let y = y.saturating_add(self.offset.y);
-                let i = (x + y * self.size.w) as usize;
-                if i < self.tiles.len() {
-                    render(&self.images[self.tiles[i]], self.px_for_tile((x, y)))
+        self.for_each_visible(|i, _, px| render(&self.images[self.tiles[i]], px));
+    }
+
+    /// Loops through all visible tiles (including partially visible ones)
+    /// calling `render` with the image, px coord, tile position and tile flags
+    ///
+    /// See [Tilemap::draw]
+    pub fn draw_detailed<F: FnMut(&Image, (isize, isize), MapPosition, u32)>(&self, mut render: F) {
+        self.for_each_visible(|i, pos, px| {
+            render(&self.images[self.tiles[i]], px, pos, self.flags[i])
+        });
+    }
+
+    /// Calls `render` once per image with the px coords of every visible tile using that image
+    ///
+    /// Designed for instanced rendering, as tiles are grouped the draw order is not kept
+    pub fn draw_batched<F: FnMut(&Image, &[(isize, isize)])>(&self, mut render: F) {
+        let mut batches = vec![vec![]; self.images.len()];
+        self.for_each_visible(|i, _, px| batches[self.tiles[i]].push(px));
+        for (i, batch) in batches.iter().enumerate() {
+            if !batch.is_empty() {
+                render(&self.images[i], batch);
+            }
+        }
+    }
+
+    /// Calls `f` with the tile index, position and px coord of every visible tile in draw order
+    fn for_each_visible<F: FnMut(usize, MapPosition, (isize, isize))>(&self, mut f: F) {
+        if self.projection != Projection::Orthogonal {
+            for (i, pos, px) in self.projected_visible_tiles() {
+                f(i, pos, px);
+            }
+            return;
+        }
+        //include tiles that are partially visible because of the subtile offset or render size
+        let extra = TilePos::new(
+            (self.subtile_offset.0.unsigned_abs() as u32 / self.tile_size.0) as i32 + 1,
+            (self.subtile_offset.1.unsigned_abs() as u32 / self.tile_size.1) as i32 + 1,
+        );
+        let area = MapRect::new(
+            self.offset - extra,
+            self.visible_size.w + extra.x as u32 * 2,
+            self.visible_size.h + extra.y as u32 * 2,
+        );
+        let mut visit = |tile: TilePos| {
+            let px = self.px_for_tile_pos(tile);
+            if self.is_px_visible(px) {
+                if let Some(pos) = self.wrap_tile_pos(tile) {
+                    f(pos.to_idx(self.size), pos, px);
                 }
             }
+        };
+        match self.draw_order {
+            DrawOrder::ColumnMajor => {
+                let end = area.end();
+                for x in area.pos.x..end.x {
+                    for y in area.pos.y..end.y {
+                        visit(TilePos::new(x, y));
+                    }
+                }
+            }
+            DrawOrder::RowMajor => area.iter().for_each(visit),
         }
     }
 
+    /// Returns true if a tile drawn at `px` would be at least partially visible
+    fn is_px_visible(&self, px: (isize, isize)) -> bool {
+        px.0 + (self.tile_size.0 as isize) > 0
+            && px.0 < self.render_size.0 as isize
+            && px.1 + (self.tile_size.1 as isize) > 0
+            && px.1 < self.render_size.1 as isize
+    }
+
+    /// Visible tiles (index, position and px coord) for non orthogonal maps, sorted in draw order
+    fn projected_visible_tiles(&self) -> Vec<(usize, MapPosition, (isize, isize))> {
+        let (render_w, render_h) = (self.render_size.0 as isize, self.render_size.1 as isize);
+        let mut min = TilePos::new(i32::MAX, i32::MAX);
+        let mut max = TilePos::new(i32::MIN, i32::MIN);
+        for corner in [(0, 0), (render_w, 0), (0, render_h), (render_w, render_h)] {
+            let tile = self.tile_pos_for_px(corner);
+            min = TilePos::new(min.x.min(tile.x), min.y.min(tile.y));
+            max = TilePos::new(max.x.max(tile.x), max.y.max(tile.y));
+        }
+        //tiles overlap so include one extra tile on each side
+        let mut area = MapRect::new(
+            min - TilePos::new(1, 1),
+            max.x.abs_diff(min.x) + 3,
+            max.y.abs_diff(min.y) + 3,
+        );
+        if !self.wrap.wraps_x() || !self.wrap.wraps_y() {
+            let mut map = MapRect::from_size(self.size);
+            if self.wrap.wraps_x() {
+                map.pos.x = area.pos.x;
+                map.w = area.w;
+            }
+            if self.wrap.wraps_y() {
+                map.pos.y = area.pos.y;
+                map.h = area.h;
+            }
+            match area.intersection(&map) {
+                None => return vec![],
+                Some(intersection) => area = intersection,
+            }
+        }
+        let mut visible = vec![];
+        for tile in area {
+            let px = self.px_for_tile_pos(tile);
+            if self.is_px_visible(px) {
+                if let Some(tile) = self.wrap_tile_pos(tile) {
+                    visible.push((tile.to_idx(self.size), tile, px));
+                }
+            }
+        }
+        visible.sort_by_key(|(_, _, (x, y))| (*y, *x));
+        visible
+    }
+
     #[inline]
     pub fn update_pos_with_offset(&self, pos: (isize, isize)) -> (isize, isize) {
         (
@@ -209,12 +496,68 @@ impl<Image: Debug + Clone> Tilemap<Image> {
     }
 
     /// Moves center of visible map to `pos`
+    ///
+    /// The visible area is kept inside the map on edges that don't wrap,
+    /// use [Tilemap::center_on_pos] to let the camera move past the edges
     pub fn center_on<P: Into<MapPosition>>(&mut self, pos: P) {
-        let pos = pos.into();
-        self.offset.x = pos.x.saturating_sub(self.visible_size.w / 2);
-        self.offset.y = pos.y.saturating_sub(self.visible_size.h / 2);
-        self.offset.x = self.offset.x.min(self.size.w - self.visible_size.w);
-        self.offset.y = self.offset.y.min(self.size.h - self.visible_size.h);
+        let offset = self.centered_offset(TilePos::from(pos.into()));
+        //orthogonal maps keep the whole screen inside the map, other projections only the top left tile
+        let (max_x, max_y) = if self.projection == Projection::Orthogonal {
+            (
+                self.size.w.saturating_sub(self.visible_size.w),
+                self.size.h.saturating_sub(self.visible_size.h),
+            )
+        } else {
+            (self.size.w.saturating_sub(1), self.size.h.saturating_sub(1))
+        };
+        let x = if self.wrap.wraps_x() {
+            offset.x
+        } else {
+            offset.x.clamp(0, max_x as i32)
+        };
+        let y = if self.wrap.wraps_y() {
+            offset.y
+        } else {
+            offset.y.clamp(0, max_y as i32)
+        };
+        self.offset = self.wrap_offset(TilePos::new(x, y));
+    }
+
+    /// Moves center of visible map to `pos`, which may be outside the map
+    ///
+    /// Unlike [Tilemap::center_on] the camera isn't kept inside the map, so it can show past the edges
+    pub fn center_on_pos<P: Into<TilePos>>(&mut self, pos: P) {
+        self.offset = self.wrap_offset(self.centered_offset(pos.into()));
+    }
+
+    /// Offset that puts `pos` in the center of the visible map
+    fn centered_offset(&self, pos: TilePos) -> TilePos {
+        if self.projection == Projection::Orthogonal {
+            pos - TilePos::new(
+                (self.visible_size.w / 2) as i32,
+                (self.visible_size.h / 2) as i32,
+            )
+        } else {
+            let center = self.projection.origin_px(pos, self.tile_size);
+            self.projection.tile_at_px(
+                (
+                    center.0 + (self.tile_size.0 as isize - self.render_size.0 as isize) / 2,
+                    center.1 + (self.tile_size.1 as isize - self.render_size.1 as isize) / 2,
+                ),
+                self.tile_size,
+            )
+        }
+    }
+
+    /// Moves `offset` inside the map on edges that wrap
+    fn wrap_offset(&self, mut offset: TilePos) -> TilePos {
+        if self.wrap.wraps_x() {
+            offset.x = offset.x.rem_euclid(self.size.w as i32);
+        }
+        if self.wrap.wraps_y() {
+            offset.y = offset.y.rem_euclid(self.size.h as i32);
+        }
+        offset
     }
 
     /// Returns a list of tiles matching `flag`
@@ -243,6 +586,12 @@ impl<Image: Debug + Clone> Tilemap<Image> {
         }
     }
 
+    /// Returns the image for `tile`, or `None` if it's outside the map
+    pub fn image_for_tile<P: Into<MapPosition>>(&self, tile: P) -> Option<&Image> {
+        self.tile_idx(tile)
+            .map(|i| self.images[self.tiles[i]].as_ref())
+    }
+
     /// Returns flag value for `tile`
     pub fn flags_for_tile<P: Into<MapPosition>>(&self, tile: P) -> u32 {
         let tile = tile.into();
@@ -273,6 +622,11 @@ impl<Image: Debug + Clone> Tilemap<Image> {
         }
     }
 
+    #[cfg(feature = "ici")]
+    pub(super) fn images_mut(&mut self) -> &mut [Rc<Image>] {
+        &mut self.images
+    }
+
     /// The default, safe start position on the map
     pub fn default_start(&self) -> MapPosition {
         self.default_start
@@ -287,10 +641,43 @@ impl<Image: Debug + Clone> Tilemap<Image> {
         self.tile_size
     }
 
+    /// Size of the area the map is drawn in, in px
+    pub fn render_size(&self) -> (u32, u32) {
+        self.render_size
+    }
+
     pub fn size(&self) -> MapSize {
         self.size
     }
 
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    /// Sets how tiles are laid out on screen
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn draw_order(&self) -> DrawOrder {
+        self.draw_order
+    }
+
+    /// Sets the order tiles are drawn in, only used by orthogonal maps
+    pub fn set_draw_order(&mut self, draw_order: DrawOrder) {
+        self.draw_order = draw_order;
+    }
+
+    pub fn wrap_mode(&self) -> WrapMode {
+        self.wrap
+    }
+
+    /// Sets which edges of the map connect to the opposite edge
+    pub fn set_wrap_mode(&mut self, wrap: WrapMode) {
+        self.wrap = wrap;
+        self.visible_size = calc_visible_size(self.render_size, self.tile_size, self.size, wrap);
+    }
+
     /// Sets a pixel offset for drawing
     /// Primarily designed for smoothing animation the map when a character or camera is moving
     pub fn set_subtile_offset(&mut self, subtile_offset: (i16, i16)) {
@@ -303,7 +690,22 @@ impl<Image: Debug + Clone> Tilemap<Image> {
     }
 }
 
+impl<Image: AnimatedImage + Debug + Clone> Tilemap<Image> {
+    /// Advance all tile animations
+    pub fn update(&mut self, timing: &Timing) {
+        self.update_secs(timing.fixed_time_step);
+    }
+
+    /// Advance all tile animations by `delta` seconds
+    pub fn update_secs(&mut self, delta: f64) {
+        for image in &mut self.images {
+            Rc::make_mut(image).update(delta);
+        }
+    }
+}
+
 impl TilemapFile {
+    /// Map tile flags are combined with [TileMetadata::default_flags] from `tileset`
     pub fn into_tilemap<Image: Debug + Clone>(
         self,
         tileset: &Tileset<Image>,
@@ -313,9 +715,13 @@ impl TilemapFile {
         let mut flag_map = HashMap::new();
         let mut missing = vec![];
         for (i, tile) in self.tiles.iter().enumerate() {
-            if let Some(img) = tileset.find_by_name(&tile.image) {
-                flag_map.insert(i, tile.flags);
-                images.push(Rc::new(img.clone()));
+            if let Some(id) = tileset.id_of(&tile.image) {
+                let default_flags = tileset
+                    .metadata(id)
+                    .map(|meta| meta.default_flags)
+                    .unwrap_or_default();
+                flag_map.insert(i, tile.flags | default_flags);
+                images.push(Rc::new(tileset.images()[id.0].as_ref().clone()));
             } else {
                 missing.push(tile.image.clone());
             }
@@ -324,13 +730,7 @@ impl TilemapFile {
             return Err(GameUtilError::InvalidTileset(self.name.clone(), missing));
         }
         let size: MapSize = (self.map[0].len() as u32, self.map.len() as u32).into();
-        let mut visible_size: MapSize = (
-            visible_area_px.0 / tileset.tilesize().0,
-            visible_area_px.1 / tileset.tilesize().1,
-        )
-            .into();
-        visible_size.w = visible_size.w.min(size.w);
-        visible_size.h = visible_size.h.min(size.h);
+        let visible_size = calc_visible_size(visible_area_px, tileset.tilesize(), size, self.wrap);
         let mut flags = vec![];
         let mut tiles = vec![];
         for row in &self.map {
@@ -345,9 +745,10 @@ impl TilemapFile {
             flags,
             size,
             visible_size,
-            offset: MapPosition::new(0, 0),
+            offset: TilePos::ZERO,
             images,
             tile_size: tileset.tilesize(),
+            render_size: visible_area_px,
             subtile_offset: (0, 0),
             default_start: self.data.start.into(),
             exits: self
@@ -356,6 +757,9 @@ impl TilemapFile {
                 .into_iter()
                 .map(MapExit::from_file)
                 .collect(),
+            projection: self.projection,
+            wrap: self.wrap,
+            draw_order: DrawOrder::ColumnMajor,
         })
     }
 }
@@ -443,6 +847,41 @@ mod test {
         );
     }
 
+    #[test]
+    fn default_flags() {
+        let mut tileset = Tileset::<&'static str>::new(
+            vec![Rc::new("sand"), Rc::new("floor"), Rc::new("wall")],
+            vec![
+                "sand".to_string(),
+                "temple_floor".to_string(),
+                "temple_wall".to_string(),
+            ],
+            (16, 16),
+        );
+        tileset.set_metadata(
+            "sand",
+            TileMetadata {
+                default_flags: 2,
+                ..TileMetadata::default()
+            },
+        );
+        tileset.set_metadata(
+            "temple_wall",
+            TileMetadata {
+                default_flags: 1,
+                ..TileMetadata::default()
+            },
+        );
+        let tilemap_file: TilemapFile = ron::from_str(SAMPLE_RON).unwrap();
+        let tilemap = tilemap_file.into_tilemap(&tileset, (64, 64)).unwrap();
+        assert_eq!(tilemap.flags_for_tile((1_u32, 1)), 2);
+        assert_eq!(tilemap.flags_for_tile((1_u32, 3)), 0);
+        assert_eq!(tilemap.flags_for_tile((0_u32, 0)), 1);
+
+        let descriptor: TileDescriptor = ron::from_str(r#"(image: "sand")"#).unwrap();
+        assert_eq!(descriptor.flags, 0);
+    }
+
     #[test]
     fn init() {
         let tileset =
@@ -460,7 +899,7 @@ mod test {
         )
         .unwrap();
 
-        assert_eq!(tilemap.offset, MapPosition::new(0, 0));
+        assert_eq!(tilemap.offset, TilePos::ZERO);
         assert_eq!(tilemap.visible_size, MapSize::new(18, 12));
         assert_eq!(tilemap.orig_px_for_tile((0_u32, 0)), (0, 0));
         assert_eq!(tilemap.orig_px_for_tile((4_u32, 4)), (64, 64));
@@ -485,13 +924,188 @@ mod test {
         .unwrap();
         tilemap.center_on(MapPosition::new(10, 6));
 
-        assert_eq!(tilemap.offset, MapPosition::new(1, 0));
+        assert_eq!(tilemap.offset, TilePos::new(1, 0));
         assert_eq!(tilemap.visible_size, MapSize::new(18, 12));
         assert_eq!(tilemap.orig_px_for_tile((0_u32, 0)), (-16, 0));
         assert_eq!(tilemap.orig_px_for_tile((4_u32, 4)), (48, 64));
         assert_eq!(tilemap.first_visible_tile(), MapPosition::new(1, 0));
     }
 
+    #[test]
+    fn offset_past_edge() {
+        let tileset =
+            Tileset::<&'static str>::new(vec![Rc::new("img")], vec!["img".to_string()], (16, 16));
+        let mut tilemap = Tilemap::new(
+            vec![0; 400],
+            vec![0; 400],
+            MapSize::new(20, 20),
+            vec!["img".to_string()],
+            tileset,
+            (300, 200),
+            MapPosition::new(0, 0),
+            vec![],
+        )
+        .unwrap();
+        tilemap.center_on_pos((0_i32, 0));
+
+        assert_eq!(tilemap.offset, TilePos::new(-9, -6));
+        assert_eq!(tilemap.first_visible_tile_pos(), TilePos::new(-9, -6));
+        assert_eq!(tilemap.first_visible_tile(), MapPosition::new(0, 0));
+        assert_eq!(tilemap.last_visible_tile_pos(), TilePos::new(9, 6));
+        assert_eq!(tilemap.orig_px_for_tile((0_u32, 0)), (144, 96));
+
+        tilemap.center_on(MapPosition::new(0, 0));
+        assert_eq!(tilemap.offset, TilePos::ZERO);
+
+        let tileset =
+            Tileset::<&'static str>::new(vec![Rc::new("img")], vec!["img".to_string()], (16, 16));
+        let mut small = Tilemap::new(
+            vec![0; 4],
+            vec![0; 4],
+            MapSize::new(2, 2),
+            vec!["img".to_string()],
+            tileset,
+            (300, 200),
+            MapPosition::new(0, 0),
+            vec![],
+        )
+        .unwrap();
+        small.center_on(MapPosition::new(1, 1));
+        assert_eq!(small.offset, TilePos::ZERO);
+    }
+
+    #[test]
+    fn projections() {
+        let tileset =
+            Tileset::<&'static str>::new(vec![Rc::new("img")], vec!["img".to_string()], (32, 16));
+        let mut tilemap = Tilemap::new(
+            vec![0; 100],
+            vec![0; 100],
+            MapSize::new(10, 10),
+            vec!["img".to_string()],
+            tileset,
+            (320, 160),
+            MapPosition::new(0, 0),
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(tilemap.tile_for_px((40, 20)), Some(MapPosition::new(1, 1)));
+        assert_eq!(tilemap.neighbours((0_u32, 0)).len(), 2);
+
+        tilemap.set_projection(Projection::Isometric(IsoLayout::Diamond));
+        assert_eq!(tilemap.px_for_tile((1_u32, 1)), (0, 16));
+        assert_eq!(tilemap.tile_for_px((16, 24)), Some(MapPosition::new(1, 1)));
+        assert_eq!(tilemap.tile_for_px((-1, 8)), None);
+        assert_eq!(tilemap.distance((0_u32, 0), (2, 3)), 5);
+        let mut drawn = vec![];
+        tilemap.draw(|_, px| drawn.push(px));
+        assert!(drawn.contains(&(0, 0)));
+        assert!(drawn.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+
+        tilemap.set_projection(Projection::Hexagonal(
+            HexOrientation::Pointy,
+            HexOffset::Odd,
+        ));
+        assert_eq!(tilemap.px_for_tile((1_u32, 1)), (48, 12));
+        assert_eq!(tilemap.tile_for_px((64, 20)), Some(MapPosition::new(1, 1)));
+        assert_eq!(tilemap.neighbours((4_u32, 4)).len(), 6);
+        assert_eq!(tilemap.neighbours((0_u32, 0)).len(), 2);
+    }
+
+    #[test]
+    fn drawing() {
+        let tileset = Tileset::<&'static str>::new(
+            vec![Rc::new("a"), Rc::new("b")],
+            vec!["a".to_string(), "b".to_string()],
+            (16, 16),
+        );
+        let mut tiles = vec![0; 100];
+        tiles[11] = 1;
+        tiles[12] = 1;
+        let mut tilemap = Tilemap::new(
+            tiles,
+            vec![0; 100],
+            MapSize::new(10, 10),
+            vec!["a".to_string(), "b".to_string()],
+            tileset,
+            (40, 32),
+            MapPosition::new(0, 0),
+            vec![],
+        )
+        .unwrap();
+        tilemap.set_flag((2_u32, 1), 4);
+
+        let mut drawn = vec![];
+        tilemap.draw(|_, px| drawn.push(px));
+        assert_eq!(
+            drawn,
+            vec![(0, 0), (0, 16), (16, 0), (16, 16), (32, 0), (32, 16)]
+        );
+
+        tilemap.set_draw_order(DrawOrder::RowMajor);
+        tilemap.set_subtile_offset((-4, 8));
+        let mut drawn = vec![];
+        tilemap.draw_detailed(|_, px, pos, flags| drawn.push((px, pos, flags)));
+        assert_eq!(drawn.len(), 6);
+        assert_eq!(drawn[0], ((-4, 8), MapPosition::new(0, 0), 0));
+        assert_eq!(drawn[3], ((-4, 24), MapPosition::new(0, 1), 0));
+        assert_eq!(drawn[5], ((28, 24), MapPosition::new(2, 1), 4));
+
+        let mut batches = vec![];
+        tilemap.draw_batched(|img, px| batches.push((*img, px.len())));
+        assert_eq!(batches, vec![("a", 4), ("b", 2)]);
+    }
+
+    #[test]
+    fn wrapping() {
+        let tileset = Tileset::<&'static str>::new(
+            vec![Rc::new("a"), Rc::new("b")],
+            vec!["a".to_string(), "b".to_string()],
+            (16, 16),
+        );
+        let mut tiles = vec![0; 100];
+        tiles[9] = 1;
+        let mut tilemap = Tilemap::new(
+            tiles,
+            vec![0; 100],
+            MapSize::new(10, 10),
+            vec!["a".to_string(), "b".to_string()],
+            tileset,
+            (64, 64),
+            MapPosition::new(0, 0),
+            vec![],
+        )
+        .unwrap();
+        tilemap.set_flag((3_u32, 0), 1);
+        assert!(!tilemap.is_inside((13_u32, 0)));
+        assert!(!tilemap.tile_has_flag((13_u32, 0), 1));
+        assert_eq!(tilemap.wrap_tile_pos((-1, 3)), None);
+        assert_eq!(tilemap.px_for_tile_pos((-1, 3)), (-16, 48));
+        assert_eq!(tilemap.tile_pos_for_px((-1, 50)), TilePos::new(-1, 3));
+
+        tilemap.set_wrap_mode(WrapMode::Horizontal);
+        assert!(tilemap.is_inside((13_u32, 0)));
+        assert!(!tilemap.is_inside((3_u32, 10)));
+        assert_eq!(tilemap.wrap_tile_pos((-1, 3)), Some(MapPosition::new(9, 3)));
+        assert!(tilemap.tile_has_flag((13_u32, 0), 1));
+        assert_eq!(
+            tilemap.wrap_position((13_u32, 0)),
+            Some(MapPosition::new(3, 0))
+        );
+        assert_eq!(tilemap.neighbours((0_u32, 5)).len(), 4);
+        assert!(tilemap
+            .neighbours((0_u32, 5))
+            .contains(&MapPosition::new(9, 5)));
+        assert_eq!(tilemap.distance((0_u32, 0), (9, 0)), 1);
+
+        tilemap.center_on((0_u32, 0));
+        assert_eq!(tilemap.offset, TilePos::new(8, 0));
+        let mut drawn = vec![];
+        tilemap.draw(|img, px| drawn.push((*img, px)));
+        assert!(drawn.contains(&("b", (16, 0))));
+        assert!(drawn.contains(&("a", (32, 0))));
+    }
+
     #[allow(non_snake_case)]
     #[test]
     fn flags() {
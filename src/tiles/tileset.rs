@@ -1,7 +1,61 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::rc::Rc;
 
+/// Position of a tile in a [Tileset]
+///
+/// Only valid for the tileset it came from
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct TileId(pub usize);
+
+/// Area of a tile that blocks movement
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+pub enum CollisionShape {
+    #[default]
+    None,
+    /// The whole tile
+    Full,
+    /// Part of the tile, in px from the top left of the tile
+    Rect { x: u32, y: u32, w: u32, h: u32 },
+}
+
+/// Animation made of other tiles in the tileset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileAnimation {
+    /// Names of tiles for each frame
+    pub frames: Vec<String>,
+    /// Seconds each frame is shown for
+    pub per_frame: f64,
+}
+
+/// Extra information about a tile
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct TileMetadata {
+    /// Flags added to every map tile using this image, see [TilemapFile::into_tilemap]
+    #[serde(default)]
+    pub default_flags: u32,
+    #[serde(default)]
+    pub collision: CollisionShape,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub animation: Option<TileAnimation>,
+}
+
+/// What [Tileset::merge] does when both tilesets have a tile with the same name
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum MergePolicy {
+    /// Keep the tile already in the tileset
+    #[default]
+    KeepExisting,
+    /// Replace the tile with the one being merged in, ids don't change
+    Replace,
+    /// Return an error and don't merge anything
+    Error,
+}
+
 ///collection of named tiles
 #[derive(Debug, Clone)]
 pub struct Tileset<Image: Debug + Clone> {
@@ -9,21 +63,155 @@ pub struct Tileset<Image: Debug + Clone> {
     images: Vec<Rc<Image>>,
     ///names
     names: Vec<String>,
+    ///metadata for each image
+    metadata: Vec<TileMetadata>,
+    ///alias to tile name
+    aliases: HashMap<String, String>,
+    ///name or alias to index in `images` and `names`
+    index: HashMap<String, usize>,
     ///size of tiles in px
     size: (u32, u32),
 }
 
 impl<Image: Debug + Clone> Tileset<Image> {
+    /// Tiles keep the order of `images` and `names`, if a name is repeated only the first is used for lookups
     pub fn new(images: Vec<Rc<Image>>, names: Vec<String>, size: (u32, u32)) -> Self {
+        let mut index = HashMap::with_capacity(names.len());
+        for (i, name) in names.iter().enumerate() {
+            index.entry(name.clone()).or_insert(i);
+        }
         Self {
+            metadata: vec![TileMetadata::default(); images.len()],
             images,
             names,
+            aliases: HashMap::new(),
+            index,
             size,
         }
     }
 }
 
 impl<Image: Debug + Clone> Tileset<Image> {
+    /// Add `alias` as another name for the tile `name`
+    ///
+    /// Returns false if `name` doesn't exist or `alias` is already used
+    pub fn add_alias(&mut self, alias: &str, name: &str) -> bool {
+        if self.index.contains_key(alias) {
+            return false;
+        }
+        let Some(&i) = self.index.get(name) else {
+            return false;
+        };
+        self.index.insert(alias.to_string(), i);
+        self.aliases
+            .insert(alias.to_string(), self.names[i].clone());
+        true
+    }
+
+    /// Alias to tile name
+    #[inline]
+    pub fn aliases(&self) -> &HashMap<String, String> {
+        &self.aliases
+    }
+
+    pub fn metadata(&self, id: TileId) -> Option<&TileMetadata> {
+        self.metadata.get(id.0)
+    }
+
+    pub fn metadata_by_name(&self, name: &str) -> Option<&TileMetadata> {
+        self.id_of(name).and_then(|id| self.metadata(id))
+    }
+
+    /// Returns false if `name` doesn't exist
+    pub fn set_metadata(&mut self, name: &str, metadata: TileMetadata) -> bool {
+        if let Some(&i) = self.index.get(name) {
+            self.metadata[i] = metadata;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns ids of all tiles with `tag`
+    pub fn ids_with_tag(&self, tag: &str) -> Vec<TileId> {
+        self.metadata
+            .iter()
+            .enumerate()
+            .filter(|(_, meta)| meta.tags.iter().any(|t| t == tag))
+            .map(|(i, _)| TileId(i))
+            .collect()
+    }
+
+    /// Add tiles, metadata and aliases from `other`
+    ///
+    /// New tiles are added after existing ones so existing ids stay valid
+    ///
+    /// # Errors
+    ///
+    /// [GameUtilError::TilesetMerge] if the tile sizes are different, or
+    /// if `policy` is [MergePolicy::Error] and any names or aliases are in both tilesets
+    pub fn merge(
+        &mut self,
+        other: Tileset<Image>,
+        policy: MergePolicy,
+    ) -> Result<(), GameUtilError> {
+        if self.size != other.size {
+            return Err(GameUtilError::TilesetMerge(format!(
+                "tile sizes are different: {:?} and {:?}",
+                self.size, other.size
+            )));
+        }
+        if policy == MergePolicy::Error {
+            let conflicts: Vec<&String> = other
+                .names
+                .iter()
+                .chain(other.aliases.keys())
+                .filter(|name| self.index.contains_key(*name))
+                .collect();
+            if !conflicts.is_empty() {
+                return Err(GameUtilError::TilesetMerge(format!(
+                    "tiles in both: {conflicts:?}"
+                )));
+            }
+        }
+
+        let mut tiles = other
+            .names
+            .into_iter()
+            .zip(other.images)
+            .zip(other.metadata);
+        for ((name, image), metadata) in &mut tiles {
+            match self.index.get(&name) {
+                Some(_) if policy == MergePolicy::KeepExisting => {}
+                Some(&i) if !self.aliases.contains_key(&name) => {
+                    self.images[i] = image;
+                    self.metadata[i] = metadata;
+                }
+                _ => {
+                    self.aliases.remove(&name);
+                    self.index.insert(name.clone(), self.images.len());
+                    self.images.push(image);
+                    self.names.push(name);
+                    self.metadata.push(metadata);
+                }
+            }
+        }
+        for (alias, name) in other.aliases {
+            if policy == MergePolicy::Replace && self.aliases.contains_key(&alias) {
+                self.index.remove(&alias);
+                self.aliases.remove(&alias);
+            }
+            self.add_alias(&alias, &name);
+        }
+        Ok(())
+    }
+}
+
+impl<Image: Debug + Clone> Tileset<Image> {
+    #[deprecated(
+        since = "0.6.0",
+        note = "allocates a new map on every call, use Tileset::iter or Tileset::find_by_name"
+    )]
     pub fn as_hashmap(&self) -> HashMap<String, Rc<Image>> {
         let mut map = HashMap::new();
         for (i, name) in self.names.iter().enumerate() {
@@ -32,11 +220,40 @@ impl<Image: Debug + Clone> Tileset<Image> {
         map
     }
 
+    /// Tile names and images in id order, names may be repeated
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Rc<Image>)> {
+        self.names
+            .iter()
+            .map(|name| name.as_str())
+            .zip(self.images.iter())
+    }
+
     pub fn find_by_name(&self, name: &str) -> Option<&Image> {
-        if let Some(i) = self.names.iter().position(|value| value == name) {
-            return Some(&self.images[i]);
-        }
-        None
+        self.index.get(name).map(|&i| self.images[i].as_ref())
+    }
+
+    pub fn find_by_id(&self, id: TileId) -> Option<&Image> {
+        self.images.get(id.0).map(|img| img.as_ref())
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<TileId> {
+        self.index.get(name).map(|&i| TileId(i))
+    }
+
+    /// Returns the tile name (never an alias)
+    pub fn name_of(&self, id: TileId) -> Option<&str> {
+        self.names.get(id.0).map(|name| name.as_str())
+    }
+
+    /// Number of tiles
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
     }
 
     #[inline]
@@ -54,3 +271,106 @@ impl<Image: Debug + Clone> Tileset<Image> {
         self.size
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let tileset = Tileset::new(
+            vec![Rc::new('a'), Rc::new('b'), Rc::new('c')],
+            vec![
+                String::from("first"),
+                String::from("second"),
+                String::from("first"),
+            ],
+            (1, 1),
+        );
+        assert_eq!(tileset.len(), 3);
+        assert_eq!(tileset.find_by_name("first"), Some(&'a'));
+        assert_eq!(tileset.find_by_name("second"), Some(&'b'));
+        assert_eq!(tileset.find_by_name("third"), None);
+        assert_eq!(tileset.id_of("second"), Some(TileId(1)));
+        assert_eq!(tileset.find_by_id(TileId(2)), Some(&'c'));
+        assert_eq!(tileset.find_by_id(TileId(3)), None);
+        assert_eq!(tileset.name_of(TileId(1)), Some("second"));
+        assert_eq!(
+            tileset
+                .iter()
+                .map(|(name, image)| (name, **image))
+                .collect::<Vec<_>>(),
+            vec![("first", 'a'), ("second", 'b'), ("first", 'c')]
+        );
+    }
+
+    fn tileset(names: &[(&str, char)]) -> Tileset<char> {
+        Tileset::new(
+            names.iter().map(|(_, c)| Rc::new(*c)).collect(),
+            names.iter().map(|(n, _)| n.to_string()).collect(),
+            (1, 1),
+        )
+    }
+
+    #[test]
+    fn aliases_and_metadata() {
+        let mut tileset = tileset(&[("grass", 'g'), ("water", 'w')]);
+        assert!(tileset.add_alias("sea", "water"));
+        assert!(!tileset.add_alias("sea", "grass"));
+        assert!(!tileset.add_alias("lava", "fire"));
+        assert_eq!(tileset.find_by_name("sea"), Some(&'w'));
+        assert_eq!(tileset.id_of("sea"), tileset.id_of("water"));
+        assert_eq!(
+            tileset.name_of(tileset.id_of("sea").unwrap()),
+            Some("water")
+        );
+
+        let meta = TileMetadata {
+            default_flags: 2,
+            collision: CollisionShape::Full,
+            tags: vec![String::from("liquid")],
+            animation: None,
+        };
+        assert!(tileset.set_metadata("sea", meta.clone()));
+        assert_eq!(tileset.metadata_by_name("water"), Some(&meta));
+        assert_eq!(tileset.metadata(TileId(0)), Some(&TileMetadata::default()));
+        assert_eq!(tileset.ids_with_tag("liquid"), vec![TileId(1)]);
+    }
+
+    #[test]
+    fn merging() {
+        let common = || {
+            let mut tileset = tileset(&[("grass", 'g'), ("wall", 'w')]);
+            tileset.add_alias("green", "grass");
+            tileset
+        };
+        let level = || {
+            let mut tileset = tileset(&[("wall", 'W'), ("door", 'd')]);
+            tileset.add_alias("exit", "door");
+            tileset
+        };
+
+        let mut tileset = common();
+        tileset.merge(level(), MergePolicy::KeepExisting).unwrap();
+        assert_eq!(tileset.names(), &["grass", "wall", "door"]);
+        assert_eq!(tileset.find_by_name("wall"), Some(&'w'));
+        assert_eq!(tileset.find_by_name("exit"), Some(&'d'));
+        assert_eq!(tileset.find_by_name("green"), Some(&'g'));
+
+        let mut tileset = common();
+        tileset.merge(level(), MergePolicy::Replace).unwrap();
+        assert_eq!(tileset.id_of("wall"), Some(TileId(1)));
+        assert_eq!(tileset.find_by_name("wall"), Some(&'W'));
+
+        let mut tileset = common();
+        assert!(matches!(
+            tileset.merge(level(), MergePolicy::Error),
+            Err(GameUtilError::TilesetMerge(_))
+        ));
+        assert_eq!(tileset.len(), 2);
+
+        let mut other_size = level();
+        other_size.size = (2, 2);
+        assert!(common().merge(other_size, MergePolicy::Replace).is_err());
+    }
+}
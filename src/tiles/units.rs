@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use std::num::TryFromIntError;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub struct MapSize {
@@ -12,6 +14,38 @@ pub struct MapPosition {
     pub y: u32,
 }
 
+/// Signed tile position, can be outside the map
+///
+/// Use [MapPosition::try_from] to convert to a map position
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Hash, Default)]
+pub struct TilePos {
+    pub x: i32,
+    pub y: i32,
+}
+
+pub type Vec2i = TilePos;
+
+/// Area of tiles, can be partially or completely outside the map
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Hash)]
+pub struct MapRect {
+    /// top left
+    pub pos: TilePos,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Hash)]
+pub enum Direction {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
 impl MapSize {
     #[inline]
     pub const fn new(w: u32, h: u32) -> Self {
@@ -36,8 +70,8 @@ impl MapPosition {
 
 impl MapSize {
     #[inline]
-    pub const fn count(&self) -> u32 {
-        self.w * self.h
+    pub const fn count(&self) -> u64 {
+        self.w as u64 * self.h as u64
     }
 }
 
@@ -48,6 +82,287 @@ impl MapPosition {
     }
 }
 
+impl Direction {
+    /// North, East, South, West
+    pub const CARDINAL: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    /// All directions, clockwise from North
+    pub const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::NorthEast,
+        Direction::East,
+        Direction::SouthEast,
+        Direction::South,
+        Direction::SouthWest,
+        Direction::West,
+        Direction::NorthWest,
+    ];
+
+    /// Offset to move one tile in this direction (y increases going south)
+    pub const fn offset(&self) -> TilePos {
+        match self {
+            Direction::North => TilePos::new(0, -1),
+            Direction::NorthEast => TilePos::new(1, -1),
+            Direction::East => TilePos::new(1, 0),
+            Direction::SouthEast => TilePos::new(1, 1),
+            Direction::South => TilePos::new(0, 1),
+            Direction::SouthWest => TilePos::new(-1, 1),
+            Direction::West => TilePos::new(-1, 0),
+            Direction::NorthWest => TilePos::new(-1, -1),
+        }
+    }
+
+    pub const fn opposite(&self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::NorthEast => Direction::SouthWest,
+            Direction::East => Direction::West,
+            Direction::SouthEast => Direction::NorthWest,
+            Direction::South => Direction::North,
+            Direction::SouthWest => Direction::NorthEast,
+            Direction::West => Direction::East,
+            Direction::NorthWest => Direction::SouthEast,
+        }
+    }
+}
+
+impl TilePos {
+    pub const ZERO: TilePos = TilePos::new(0, 0);
+
+    #[inline]
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// Position one tile away in `direction`
+    #[inline]
+    pub fn step(&self, direction: Direction) -> TilePos {
+        *self + direction.offset()
+    }
+
+    /// Positions north, east, south and west of this one
+    pub fn neighbours_4(&self) -> [TilePos; 4] {
+        Direction::CARDINAL.map(|dir| self.step(dir))
+    }
+
+    /// All positions surrounding this one, clockwise from north
+    pub fn neighbours_8(&self) -> [TilePos; 8] {
+        Direction::ALL.map(|dir| self.step(dir))
+    }
+
+    /// Distance when only moving horizontally or vertically
+    #[inline]
+    pub fn manhattan(&self, other: TilePos) -> u32 {
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
+    }
+
+    /// Distance when diagonal moves are allowed
+    #[inline]
+    pub fn chebyshev(&self, other: TilePos) -> u32 {
+        self.x.abs_diff(other.x).max(self.y.abs_diff(other.y))
+    }
+
+    /// Straight line distance
+    #[inline]
+    pub fn euclidean(&self, other: TilePos) -> f64 {
+        let x = self.x as f64 - other.x as f64;
+        let y = self.y as f64 - other.y as f64;
+        x.hypot(y)
+    }
+}
+
+impl MapRect {
+    #[inline]
+    pub const fn new(pos: TilePos, w: u32, h: u32) -> Self {
+        Self { pos, w, h }
+    }
+
+    /// Rect covering all of a map of `size`
+    #[inline]
+    pub const fn from_size(size: MapSize) -> Self {
+        Self::new(TilePos::ZERO, size.w, size.h)
+    }
+
+    /// Exclusive bottom right, clamped to `i32::MAX` for rects that extend past it
+    pub fn end(&self) -> TilePos {
+        let end = |start: i32, len: u32| {
+            i64::from(start)
+                .checked_add(i64::from(len))
+                .and_then(|end| i32::try_from(end).ok())
+                .unwrap_or(i32::MAX)
+        };
+        TilePos::new(end(self.pos.x, self.w), end(self.pos.y, self.h))
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.w == 0 || self.h == 0
+    }
+
+    #[inline]
+    pub const fn count(&self) -> u64 {
+        self.w as u64 * self.h as u64
+    }
+
+    pub fn contains<P: Into<TilePos>>(&self, pos: P) -> bool {
+        let pos = pos.into();
+        let end = self.end();
+        pos.x >= self.pos.x && pos.y >= self.pos.y && pos.x < end.x && pos.y < end.y
+    }
+
+    pub fn intersects(&self, other: &MapRect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Area covered by both rects, or None if they don't overlap
+    pub fn intersection(&self, other: &MapRect) -> Option<MapRect> {
+        let start = TilePos::new(self.pos.x.max(other.pos.x), self.pos.y.max(other.pos.y));
+        let (end, other_end) = (self.end(), other.end());
+        let end = TilePos::new(end.x.min(other_end.x), end.y.min(other_end.y));
+        if start.x < end.x && start.y < end.y {
+            Some(MapRect::new(
+                start,
+                end.x.abs_diff(start.x),
+                end.y.abs_diff(start.y),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// All positions in the rect, row by row
+    ///
+    /// Positions past `i32::MAX` are skipped
+    pub fn iter(&self) -> MapRectIter {
+        let end = self.end();
+        MapRectIter {
+            rect: MapRect::new(
+                self.pos,
+                end.x.abs_diff(self.pos.x),
+                end.y.abs_diff(self.pos.y),
+            ),
+            next: 0,
+        }
+    }
+}
+
+pub struct MapRectIter {
+    rect: MapRect,
+    next: u64,
+}
+
+impl Iterator for MapRectIter {
+    type Item = TilePos;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rect.is_empty() || self.next >= self.rect.count() {
+            return None;
+        }
+        //the rect is clamped in MapRect::iter so these fit in i32
+        let w = u64::from(self.rect.w);
+        let pos = TilePos::new(
+            (i64::from(self.rect.pos.x) + (self.next % w) as i64) as i32,
+            (i64::from(self.rect.pos.y) + (self.next / w) as i64) as i32,
+        );
+        self.next += 1;
+        Some(pos)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = usize::try_from(self.rect.count().saturating_sub(self.next)).ok();
+        (remaining.unwrap_or(usize::MAX), remaining)
+    }
+}
+
+impl IntoIterator for MapRect {
+    type Item = TilePos;
+    type IntoIter = MapRectIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Add for TilePos {
+    type Output = TilePos;
+
+    #[inline]
+    fn add(self, rhs: TilePos) -> Self::Output {
+        TilePos::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl Sub for TilePos {
+    type Output = TilePos;
+
+    #[inline]
+    fn sub(self, rhs: TilePos) -> Self::Output {
+        TilePos::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl Mul<i32> for TilePos {
+    type Output = TilePos;
+
+    #[inline]
+    fn mul(self, rhs: i32) -> Self::Output {
+        TilePos::new(self.x * rhs, self.y * rhs)
+    }
+}
+
+impl Neg for TilePos {
+    type Output = TilePos;
+
+    #[inline]
+    fn neg(self) -> Self::Output {
+        TilePos::new(-self.x, -self.y)
+    }
+}
+
+impl AddAssign for TilePos {
+    #[inline]
+    fn add_assign(&mut self, rhs: TilePos) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for TilePos {
+    #[inline]
+    fn sub_assign(&mut self, rhs: TilePos) {
+        *self = *self - rhs;
+    }
+}
+
+impl From<MapPosition> for TilePos {
+    /// Positions past `i32::MAX` will wrap
+    #[inline]
+    fn from(value: MapPosition) -> Self {
+        TilePos::new(value.x as i32, value.y as i32)
+    }
+}
+
+impl TryFrom<TilePos> for MapPosition {
+    type Error = TryFromIntError;
+
+    /// Fails if either coord is negative
+    #[inline]
+    fn try_from(value: TilePos) -> Result<Self, Self::Error> {
+        Ok(MapPosition::new(value.x.try_into()?, value.y.try_into()?))
+    }
+}
+
+impl From<Direction> for TilePos {
+    #[inline]
+    fn from(value: Direction) -> Self {
+        value.offset()
+    }
+}
+
 macro_rules! from_num {
     ($num:ty) => {
         impl From<($num, $num)> for MapPosition {
@@ -71,12 +386,47 @@ macro_rules! from_num {
     };
 }
 
+macro_rules! from_signed_num {
+    ($num:ty) => {
+        impl From<($num, $num)> for TilePos {
+            #[inline]
+            fn from(value: ($num, $num)) -> Self {
+                TilePos {
+                    x: value.0 as i32,
+                    y: value.1 as i32,
+                }
+            }
+        }
+    };
+}
+
+macro_rules! try_from_signed_num {
+    ($num:ty) => {
+        impl TryFrom<($num, $num)> for TilePos {
+            type Error = TryFromIntError;
+
+            /// Fails if either coord doesn't fit in an `i32`
+            #[inline]
+            fn try_from(value: ($num, $num)) -> Result<Self, Self::Error> {
+                Ok(TilePos::new(value.0.try_into()?, value.1.try_into()?))
+            }
+        }
+    };
+}
+
 from_num!(u64);
 from_num!(u32);
 from_num!(u16);
 from_num!(u8);
 from_num!(usize);
 
+from_signed_num!(i32);
+from_signed_num!(i16);
+from_signed_num!(i8);
+
+try_from_signed_num!(i64);
+try_from_signed_num!(isize);
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -102,4 +452,73 @@ mod test {
         assert_eq!(MapPosition::from_idx(15, size1), MapPosition::new(3, 3));
         assert_eq!(MapPosition::from_idx(15, size2), MapPosition::new(7, 1));
     }
+
+    #[test]
+    fn tile_pos() {
+        let pos = TilePos::new(2, -3);
+        assert_eq!(pos + TilePos::new(-4, 1), TilePos::new(-2, -2));
+        assert_eq!(pos - TilePos::new(-4, 1), TilePos::new(6, -4));
+        assert_eq!(-pos * 2, TilePos::new(-4, 6));
+        assert_eq!(pos.step(Direction::NorthWest), TilePos::new(1, -4));
+        assert_eq!(pos.manhattan(TilePos::ZERO), 5);
+        assert_eq!(pos.chebyshev(TilePos::ZERO), 3);
+        assert_eq!(TilePos::new(3, 4).euclidean(TilePos::ZERO), 5.0);
+        assert!(MapPosition::try_from(pos).is_err());
+        assert_eq!(
+            MapPosition::try_from(TilePos::new(2, 3)).unwrap(),
+            MapPosition::new(2, 3)
+        );
+        assert_eq!(TilePos::from(MapPosition::new(2, 3)), TilePos::new(2, 3));
+        for dir in Direction::ALL {
+            assert_eq!(pos.step(dir).step(dir.opposite()), pos);
+        }
+    }
+
+    #[test]
+    fn rect() {
+        let rect = MapRect::new(TilePos::new(-1, -1), 3, 2);
+        assert!(rect.contains((-1_i32, 0)));
+        assert!(!rect.contains((2_i32, 0)));
+        assert_eq!(
+            rect.intersection(&MapRect::from_size(MapSize::new(10, 10))),
+            Some(MapRect::new(TilePos::ZERO, 2, 1))
+        );
+        assert!(!rect.intersects(&MapRect::new(TilePos::new(2, 0), 5, 5)));
+        assert_eq!(
+            rect.iter().collect::<Vec<_>>(),
+            vec![
+                TilePos::new(-1, -1),
+                TilePos::new(0, -1),
+                TilePos::new(1, -1),
+                TilePos::new(-1, 0),
+                TilePos::new(0, 0),
+                TilePos::new(1, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn large_rect() {
+        let rect = MapRect::new(TilePos::ZERO, 100000, 100000);
+        assert_eq!(rect.count(), 10_000_000_000);
+        assert_eq!(MapSize::new(100000, 100000).count(), 10_000_000_000);
+        assert_eq!(rect.end(), TilePos::new(100000, 100000));
+        assert!(rect.contains((99999_i32, 99999)));
+
+        let rect = MapRect::new(TilePos::new(i32::MAX - 1, i32::MIN), u32::MAX, u32::MAX);
+        assert_eq!(rect.end(), TilePos::new(i32::MAX, i32::MAX));
+        assert!(rect.contains((i32::MAX - 1, 0)));
+        assert_eq!(rect.intersection(&rect).unwrap().w, 1);
+        assert_eq!(rect.intersection(&rect).unwrap().h, u32::MAX);
+        let mut iter = rect.iter();
+        assert_eq!(iter.next(), Some(TilePos::new(i32::MAX - 1, i32::MIN)));
+        assert_eq!(iter.next(), Some(TilePos::new(i32::MAX - 1, i32::MIN + 1)));
+
+        assert_eq!(TilePos::try_from((3_i64, -4_i64)), Ok(TilePos::new(3, -4)));
+        assert!(TilePos::try_from((1_i64 << 40, 0_i64)).is_err());
+        assert_eq!(
+            TilePos::try_from((2_isize, -1_isize)),
+            Ok(TilePos::new(2, -1))
+        );
+    }
 }